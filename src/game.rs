@@ -1,9 +1,19 @@
-use std::{
-    collections::HashMap,
-    fmt::Display,
-    mem::{discriminant, Discriminant},
-    ops,
+use std::{fmt::Display, ops};
+
+use bitboard::{
+    magic::{bishop_attacks, rook_attacks},
+    squares, to_space, to_square,
 };
+use fen::FenError;
+
+pub mod bitboard;
+pub mod epd;
+pub mod fen;
+pub mod perft;
+pub mod pgn;
+pub mod san;
+pub mod uci;
+mod zobrist;
 
 // Directional movement offsets using 0x88 board representation
 // Missing directions are inverts of these (So we subtract)
@@ -14,17 +24,28 @@ const RIGHT: usize = 1;
 const KNIGHT_MOVES: [usize; 4] = [14, 18, 31, 33];
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-enum Piece {
+pub enum Piece {
     King,
     Queen,
-    Rook(bool),   // True if king rook
-    Knight(bool), // True if king knight
-    Bishop(bool), // True if king bishop
-    Pawn(u8),     // Store the pawn file
+    Rook,
+    Knight,
+    Bishop,
+    Pawn,
+}
+
+impl Piece {
+    const ALL: [Piece; 6] = [
+        Piece::King,
+        Piece::Queen,
+        Piece::Rook,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Pawn,
+    ];
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-enum Side {
+pub enum Side {
     White,
     Black,
 }
@@ -41,6 +62,21 @@ impl ops::Not for Side {
     }
 }
 
+// Whether the game is over, and why
+// Threefold repetition and the fifty move rule only give a player the right to claim a draw,
+// the others end the game on their own
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    Ongoing,
+    Checkmate(Side), // The winning side
+    Stalemate,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+    SeventyFiveMove,
+    FivefoldRepetition,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Space {
     piece: Piece,
@@ -53,35 +89,302 @@ impl Display for Space {
             Side::White => match self.piece {
                 Piece::King => write!(f, "K"),
                 Piece::Queen => write!(f, "Q"),
-                Piece::Rook(_) => write!(f, "R"),
-                Piece::Knight(_) => write!(f, "N"),
-                Piece::Bishop(_) => write!(f, "B"),
-                Piece::Pawn(_) => write!(f, "P"),
+                Piece::Rook => write!(f, "R"),
+                Piece::Knight => write!(f, "N"),
+                Piece::Bishop => write!(f, "B"),
+                Piece::Pawn => write!(f, "P"),
             },
             Side::Black => match self.piece {
                 Piece::King => write!(f, "k"),
                 Piece::Queen => write!(f, "q"),
-                Piece::Rook(_) => write!(f, "r"),
-                Piece::Knight(_) => write!(f, "n"),
-                Piece::Bishop(_) => write!(f, "b"),
-                Piece::Pawn(_) => write!(f, "p"),
+                Piece::Rook => write!(f, "r"),
+                Piece::Knight => write!(f, "n"),
+                Piece::Bishop => write!(f, "b"),
+                Piece::Pawn => write!(f, "p"),
             },
         }
     }
 }
 
+// Flags describing what a move does beyond moving a piece
+const CAPTURE: u8 = 1;
+const CASTLE: u8 = 2;
+const EN_PASSANT: u8 = 4;
+const DOUBLE_PUSH: u8 = 8;
+
+// A move of a piece from one 0x88 space to another
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    from: u8,
+    to: u8,
+    promotion: Option<Piece>,
+    flags: u8,
+}
+
+impl Move {
+    pub fn new(from: usize, to: usize) -> Self {
+        Move {
+            from: from as u8,
+            to: to as u8,
+            promotion: None,
+            flags: 0,
+        }
+    }
+
+    pub fn with_promotion(self, piece: Piece) -> Self {
+        Move {
+            promotion: Some(piece),
+            ..self
+        }
+    }
+
+    pub fn from(&self) -> usize {
+        self.from as usize
+    }
+
+    pub fn to(&self) -> usize {
+        self.to as usize
+    }
+
+    pub fn promotion(&self) -> Option<Piece> {
+        self.promotion
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.flags & CAPTURE != 0
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.flags & CASTLE != 0
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.flags & EN_PASSANT != 0
+    }
+
+    pub fn is_double_push(&self) -> bool {
+        self.flags & DOUBLE_PUSH != 0
+    }
+
+    // The generated move this one stands for, so a move built from its spaces picks up what it does, like
+    // castling or a double push
+    // An exact match wins, which keeps a Chess960 king move apart from castling to the same space
+    fn find_in(self, moves: Vec<Move>) -> Option<Move> {
+        moves
+            .iter()
+            .find(|m| **m == self)
+            .or_else(|| {
+                moves.iter().find(|m| {
+                    m.from == self.from && m.to == self.to && m.promotion == self.promotion
+                })
+            })
+            .copied()
+    }
+}
+
+// The state a move destroys, kept by do_move so undo_move can restore it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Undo {
+    captured: Option<Piece>,
+    en_passant: Option<usize>,
+    halfmove_clock: usize,
+    castling: [bool; 4],
+    check: [bool; 2],
+}
+
+// The operations shared by each board representation, so they can be swapped and checked against each other
+pub trait Position: Clone + Display {
+    fn from_fen(raw_game: String) -> Result<Self, FenError>;
+    fn to_fen(&self) -> String;
+    fn current_player(&self) -> Side;
+    fn in_check(&self) -> bool;
+    fn is_attacked(&self, space: usize, by: Side) -> bool;
+    fn attackers(&self, space: usize, by: Side) -> Vec<usize>;
+    fn checkers(&self) -> Vec<usize>;
+    fn generate_moves(&self) -> Vec<Move>;
+    fn make_move(&self, m: Move) -> Option<Self>;
+    fn do_move(&mut self, m: Move) -> Undo;
+    fn undo_move(&mut self, m: Move, undo: Undo);
+}
+
+// Most pieces a side can hold of one kind, two rooks plus all eight pawns promoted
+// from_fen turns away material that promotions couldn't have made, so promoting never goes past it
+const MAX_PIECES: usize = 10;
+
+// The spaces held by each kind of piece for one side, complementing the board array for piece to space lookups
+#[derive(Clone, Debug, Eq)]
+struct PieceList {
+    spaces: [[u8; MAX_PIECES]; 6],
+    counts: [u8; 6],
+}
+
+// Lists are equal when they hold the same spaces, whatever order they were added and removed in
+impl PartialEq for PieceList {
+    fn eq(&self, other: &Self) -> bool {
+        Piece::ALL.iter().all(|piece| {
+            let count = self.counts[*piece as usize] as usize;
+            count == other.counts[*piece as usize] as usize
+                && self
+                    .spaces(*piece)
+                    .all(|space| other.spaces[*piece as usize][..count].contains(&(space as u8)))
+        })
+    }
+}
+
+impl PieceList {
+    fn new() -> Self {
+        PieceList {
+            spaces: [[0; MAX_PIECES]; 6],
+            counts: [0; 6],
+        }
+    }
+
+    fn from_board(board: &[Option<Space>; 128], side: Side) -> Self {
+        let mut pieces = PieceList::new();
+        for (position, space) in board.iter().enumerate() {
+            if let Some(space) = space {
+                if space.side == side {
+                    pieces.add(space.piece, position);
+                }
+            }
+        }
+
+        pieces
+    }
+
+    #[inline(always)]
+    fn add(&mut self, piece: Piece, position: usize) {
+        let count = &mut self.counts[piece as usize];
+        self.spaces[piece as usize][*count as usize] = position as u8;
+        *count += 1;
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, piece: Piece, position: usize) {
+        let count = self.counts[piece as usize] as usize;
+        let spaces = &mut self.spaces[piece as usize];
+        if let Some(i) = spaces[..count].iter().position(|s| *s as usize == position) {
+            // Order doesn't matter, so fill the gap with the last piece
+            spaces[i] = spaces[count - 1];
+            self.counts[piece as usize] -= 1;
+        }
+    }
+
+    #[inline(always)]
+    fn relocate(&mut self, piece: Piece, src: usize, dest: usize) {
+        let count = self.counts[piece as usize] as usize;
+        if let Some(space) = self.spaces[piece as usize][..count]
+            .iter_mut()
+            .find(|s| **s as usize == src)
+        {
+            *space = dest as u8;
+        }
+    }
+
+    #[inline(always)]
+    fn king(&self) -> usize {
+        self.spaces[Piece::King as usize][0] as usize
+    }
+
+    fn spaces(&self, piece: Piece) -> impl Iterator<Item = usize> + '_ {
+        self.spaces[piece as usize][..self.counts[piece as usize] as usize]
+            .iter()
+            .map(|s| *s as usize)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Piece, usize)> + '_ {
+        Piece::ALL
+            .into_iter()
+            .flat_map(move |piece| self.spaces(piece).map(move |s| (piece, s)))
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.counts.iter().map(|c| *c as usize).sum()
+    }
+
+    #[cfg(test)]
+    fn contains(&self, piece: Piece, position: usize) -> bool {
+        self.spaces(piece).any(|s| s == position)
+    }
+}
+
+// Which moves to generate, so a search can try the moves most likely to be good first
+// Captures include every promotion, so together with the quiet moves they make up all the moves
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    All,
+    Captures,
+    Quiets,
+    QuietChecks,
+}
+
+// What limits the moves of everything but the king, worked out once before generating
+struct Restrictions {
+    evasions: u128, // Spaces that capture or block a checking piece, all of them when not in check
+    pins: Vec<(usize, u128)>, // Pinned pieces, with the spaces from the king to the pinning piece
+    stage: Stage,
+}
+
+impl Restrictions {
+    #[inline(always)]
+    fn allows(&self, src: usize, dest: usize) -> bool {
+        let pin = self
+            .pins
+            .iter()
+            .find(|(pinned, _)| *pinned == src)
+            .map_or(!0, |(_, ray)| *ray);
+
+        self.evasions & pin & (1 << dest) != 0
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Player {
-    pieces: HashMap<Piece, usize>,
+    pieces: PieceList,
     check: bool,
+    castle_kingside: bool,
+    castle_queenside: bool,
+    rook_files: [usize; 2], // Where the kingside and queenside rooks castle from, which Chess960 moves around
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Game {
     board: [Option<Space>; 128], // TODO: Look into bijective map to replace this
+    occupied: u64, // Every square holding a piece, numbered as the bitboards do, for the magic lookups
     current_player: Side,
+    en_passant: Option<usize>, // The space skipped by a pawn double move on the last turn
+    halfmove_clock: usize,     // Moves since the last capture or pawn move, for the fifty move rule
+    fullmove_number: usize,
     white: Player,
     black: Player,
+    history: Vec<u64>, // Keys of every earlier position, oldest first
+    key: u64,
+}
+
+// Convert between algebraic names like "e3" and 0x88 spaces
+fn parse_space(name: &str) -> Option<usize> {
+    let mut runes = name.chars();
+    let file = match runes.next()? {
+        rune @ 'a'..='h' => rune as usize - 'a' as usize,
+        _ => return None,
+    };
+    let rank = match runes.next()? {
+        rune @ '1'..='8' => rune as usize - '1' as usize,
+        _ => return None,
+    };
+    if runes.next().is_some() {
+        return None;
+    }
+
+    Some(rank * UP + file)
+}
+
+fn space_name(space: usize) -> String {
+    let file = (b'a' + (space & 0x07) as u8) as char;
+    let rank = (b'1' + (space >> 4) as u8) as char;
+
+    format!("{}{}", file, rank)
 }
 
 impl Display for Game {
@@ -102,185 +405,108 @@ impl Display for Game {
     }
 }
 
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Game {
     /// Create a new game object, from the standard starting position
     #[rustfmt::skip]
     pub fn new() -> Self {
-        Game{
-            board: [
+        let board = [
                 // Rank 1
-                Some(Space { piece: Piece::Rook(false), side: Side::White }), Some(Space { piece: Piece::Knight(false), side: Side::White }), Some(Space { piece: Piece::Bishop(false), side: Side::White }),
-                Some(Space { piece: Piece::Queen, side: Side::White }), Some(Space { piece: Piece::King, side: Side::White }), Some(Space { piece: Piece::Bishop(true), side: Side::White }),
-                Some(Space { piece: Piece::Knight(true), side: Side::White }), Some(Space { piece: Piece::Rook(true), side: Side::White }), None, None, None, None, None, None, None, None,
+                Some(Space { piece: Piece::Rook, side: Side::White }), Some(Space { piece: Piece::Knight, side: Side::White }), Some(Space { piece: Piece::Bishop, side: Side::White }),
+                Some(Space { piece: Piece::Queen, side: Side::White }), Some(Space { piece: Piece::King, side: Side::White }), Some(Space { piece: Piece::Bishop, side: Side::White }),
+                Some(Space { piece: Piece::Knight, side: Side::White }), Some(Space { piece: Piece::Rook, side: Side::White }), None, None, None, None, None, None, None, None,
                 // Rank 2
-                Some(Space { piece: Piece::Pawn(0), side: Side::White }), Some(Space { piece: Piece::Pawn(1), side: Side::White }), Some(Space { piece: Piece::Pawn(2), side: Side::White }),
-                Some(Space { piece: Piece::Pawn(3), side: Side::White }), Some(Space { piece: Piece::Pawn(4), side: Side::White }), Some(Space { piece: Piece::Pawn(5), side: Side::White }),
-                Some(Space { piece: Piece::Pawn(6), side: Side::White }), Some(Space { piece: Piece::Pawn(7), side: Side::White }), None, None, None, None, None, None, None, None,
+                Some(Space { piece: Piece::Pawn, side: Side::White }), Some(Space { piece: Piece::Pawn, side: Side::White }), Some(Space { piece: Piece::Pawn, side: Side::White }),
+                Some(Space { piece: Piece::Pawn, side: Side::White }), Some(Space { piece: Piece::Pawn, side: Side::White }), Some(Space { piece: Piece::Pawn, side: Side::White }),
+                Some(Space { piece: Piece::Pawn, side: Side::White }), Some(Space { piece: Piece::Pawn, side: Side::White }), None, None, None, None, None, None, None, None,
                 // Rank 3 - 6
                 None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
                 None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
                 None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
                 None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
                 // Rank 7
-                Some(Space { piece: Piece::Pawn(0), side: Side::Black }), Some(Space { piece: Piece::Pawn(1), side: Side::Black }), Some(Space { piece: Piece::Pawn(2), side: Side::Black }),
-                Some(Space { piece: Piece::Pawn(3), side: Side::Black }), Some(Space { piece: Piece::Pawn(4), side: Side::Black }), Some(Space { piece: Piece::Pawn(5), side: Side::Black }),
-                Some(Space { piece: Piece::Pawn(6), side: Side::Black }), Some(Space { piece: Piece::Pawn(7), side: Side::Black }), None, None, None, None, None, None, None, None,
-                // Rank 8 
-                Some(Space { piece: Piece::Rook(false), side: Side::Black }), Some(Space { piece: Piece::Knight(false), side: Side::Black }), Some(Space { piece: Piece::Bishop(false), side: Side::Black }),
-                Some(Space { piece: Piece::Queen, side: Side::Black }), Some(Space { piece: Piece::King, side: Side::Black }), Some(Space { piece: Piece::Bishop(true), side: Side::Black }),
-                Some(Space { piece: Piece::Knight(true), side: Side::Black }), Some(Space { piece: Piece::Rook(true), side: Side::Black }), None, None, None, None, None, None, None, None,
-            ],
-            white: Player{ pieces: HashMap::from([(Piece::Rook(false), 0x00), (Piece::Knight(false), 0x01), (Piece::Bishop(false), 0x02),
-                                (Piece::Queen, 0x03), (Piece::King, 0x04), (Piece::Bishop(true), 0x05),
-                                (Piece::Knight(true), 0x06), (Piece::Rook(true), 0x07), (Piece::Pawn(0), 0x10),
-                                (Piece::Pawn(1), 0x11), (Piece::Pawn(2), 0x12), (Piece::Pawn(3), 0x13),
-                                (Piece::Pawn(4), 0x14), (Piece::Pawn(5), 0x15), (Piece::Pawn(6), 0x16),
-                                (Piece::Pawn(7), 0x17)]), check: false },
-            black: Player{ pieces: HashMap::from([(Piece::Rook(false), 0x70), (Piece::Knight(false), 0x71), (Piece::Bishop(false), 0x72),
-                                (Piece::Queen, 0x73), (Piece::King, 0x74), (Piece::Bishop(true), 0x75),
-                                (Piece::Knight(true), 0x76), (Piece::Rook(true), 0x77), (Piece::Pawn(0), 0x60),
-                                (Piece::Pawn(1), 0x61), (Piece::Pawn(2), 0x62), (Piece::Pawn(3), 0x63),
-                                (Piece::Pawn(4), 0x64), (Piece::Pawn(5), 0x65), (Piece::Pawn(6), 0x66),
-                                (Piece::Pawn(7), 0x67)]), check: false },
+                Some(Space { piece: Piece::Pawn, side: Side::Black }), Some(Space { piece: Piece::Pawn, side: Side::Black }), Some(Space { piece: Piece::Pawn, side: Side::Black }),
+                Some(Space { piece: Piece::Pawn, side: Side::Black }), Some(Space { piece: Piece::Pawn, side: Side::Black }), Some(Space { piece: Piece::Pawn, side: Side::Black }),
+                Some(Space { piece: Piece::Pawn, side: Side::Black }), Some(Space { piece: Piece::Pawn, side: Side::Black }), None, None, None, None, None, None, None, None,
+                // Rank 8
+                Some(Space { piece: Piece::Rook, side: Side::Black }), Some(Space { piece: Piece::Knight, side: Side::Black }), Some(Space { piece: Piece::Bishop, side: Side::Black }),
+                Some(Space { piece: Piece::Queen, side: Side::Black }), Some(Space { piece: Piece::King, side: Side::Black }), Some(Space { piece: Piece::Bishop, side: Side::Black }),
+                Some(Space { piece: Piece::Knight, side: Side::Black }), Some(Space { piece: Piece::Rook, side: Side::Black }), None, None, None, None, None, None, None, None,
+            ];
+
+        let mut game = Game{
+            board,
+            occupied: Game::occupancy(&board),
+            white: Player{ pieces: PieceList::from_board(&board, Side::White), check: false, castle_kingside: true, castle_queenside: true, rook_files: [7, 0] },
+            black: Player{ pieces: PieceList::from_board(&board, Side::Black), check: false, castle_kingside: true, castle_queenside: true, rook_files: [7, 0] },
             current_player: Side::White,
-        }
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: vec![],
+            key: 0,
+        };
+        game.key = game.compute_key();
+
+        game
     }
 
-    // Create a new game object, using A Forsyth–Edwards Notation string
-    pub fn from_fen(raw_game: String) -> Game {
-        let mut game = Game {
-            board: [None; 128],
-            white: Player {
-                pieces: HashMap::new(),
-                check: false,
-            },
-            black: Player {
-                pieces: HashMap::new(),
-                check: false,
-            },
-            current_player: Side::White,
+    // Create a Chess960 game from one of the 960 starting positions, numbered as Scharnagl does so 518 is the standard one
+    pub fn from_chess960(index: usize) -> Option<Self> {
+        if index >= 960 {
+            return None;
+        }
+
+        // The bishops go on one light and one dark file, then each other piece takes part of what is left
+        // of the index to pick which of the free files it goes on
+        let mut rank = [None; 8];
+        let (index, light) = (index / 4, index % 4);
+        let (index, dark) = (index / 4, index % 4);
+        rank[light * 2 + 1] = Some('b');
+        rank[dark * 2] = Some('b');
+        let mut place = |piece: char, nth: usize| {
+            let file = (0..8)
+                .filter(|file| rank[*file].is_none())
+                .nth(nth)
+                .unwrap();
+            rank[file] = Some(piece);
         };
-        let mut fen = raw_game.split(" ");
-
-        let mut white_pawn = 0;
-        let mut black_pawn = 0;
-        let mut white_knight = false;
-        let mut black_knight = false;
-        let mut white_bishop = false;
-        let mut black_bishop = false;
-        let mut white_rook = false;
-        let mut black_rook = false;
-        let mut space = 0x70;
-        for rune in fen.next().unwrap().chars() {
-            match rune {
-                'P' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Pawn(white_pawn),
-                        side: Side::White,
-                    });
-                    game.white.pieces.insert(Piece::Pawn(white_pawn), space);
-                    white_pawn += 1;
-                }
-                'N' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Knight(white_knight),
-                        side: Side::White,
-                    });
-                    game.white.pieces.insert(Piece::Knight(white_knight), space);
-                    white_knight = true;
-                }
-                'B' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Bishop(white_bishop),
-                        side: Side::White,
-                    });
-                    game.white.pieces.insert(Piece::Bishop(white_bishop), space);
-                    white_bishop = true;
-                }
-                'R' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Rook(white_rook),
-                        side: Side::White,
-                    });
-                    game.white.pieces.insert(Piece::Rook(white_rook), space);
-                    white_rook = true;
-                }
-                'Q' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Queen,
-                        side: Side::White,
-                    });
-                    game.white.pieces.insert(Piece::Queen, space);
-                }
-                'K' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::King,
-                        side: Side::White,
-                    });
-                    game.white.pieces.insert(Piece::King, space);
-                }
-                'p' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Pawn(black_pawn),
-                        side: Side::Black,
-                    });
-                    game.black.pieces.insert(Piece::Pawn(black_pawn), space);
-                    black_pawn += 1;
-                }
-                'n' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Knight(black_knight),
-                        side: Side::Black,
-                    });
-                    game.black.pieces.insert(Piece::Knight(black_knight), space);
-                    black_knight = true;
-                }
-                'b' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Bishop(black_bishop),
-                        side: Side::Black,
-                    });
-                    game.black.pieces.insert(Piece::Bishop(black_bishop), space);
-                    black_bishop = true;
-                }
-                'r' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Rook(black_rook),
-                        side: Side::Black,
-                    });
-                    game.black.pieces.insert(Piece::Rook(black_rook), space);
-                    black_rook = true;
-                }
-                'q' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::Queen,
-                        side: Side::Black,
-                    });
-                    game.black.pieces.insert(Piece::Queen, space);
-                }
-                'k' => {
-                    game.board[space] = Some(Space {
-                        piece: Piece::King,
-                        side: Side::Black,
-                    });
-                    game.black.pieces.insert(Piece::King, space);
-                }
-                '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' => {
-                    let skip = rune.to_digit(10).unwrap() as usize;
-                    space += skip - 1;
-                }
-                '/' => {
-                    space -= 0x18; // Move to the start of the next rank
-                    continue; // And skip the +1
-                }
-                _ => panic!("unexpected character in FEN string"),
-            }
-            space += 1;
+        let (index, queen) = (index / 6, index % 6);
+        place('q', queen);
+
+        // Every way to pick two of the five free files, placing the later knight first keeps the earlier file's place
+        let (first, second) = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ][index];
+        place('n', second);
+        place('n', first);
+
+        // The king always starts between the rooks
+        for piece in ['r', 'k', 'r'] {
+            place(piece, 0);
         }
 
-        game.white.check = game.king_check(Side::White, game.white.pieces[&Piece::King]);
-        game.black.check = game.king_check(Side::Black, game.black.pieces[&Piece::King]);
-        game
+        let black: String = rank.iter().map(|piece| piece.unwrap()).collect();
+        Game::from_fen(format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            black,
+            black.to_uppercase()
+        ))
+        .ok()
     }
 
     #[inline(always)]
@@ -291,19 +517,118 @@ impl Game {
         }
     }
 
-    pub fn generate_ply(&self) -> Vec<Game> {
+    #[inline(always)]
+    fn get_side(&self, side: Side) -> &Player {
+        match side {
+            Side::White => &self.white,
+            Side::Black => &self.black,
+        }
+    }
+
+    #[inline(always)]
+    fn get_side_mut(&mut self, side: Side) -> &mut Player {
+        match side {
+            Side::White => &mut self.white,
+            Side::Black => &mut self.black,
+        }
+    }
+
+    // Is the game over, checking first for the outcomes that end it outright
+    pub fn status(&self) -> Status {
+        if self.generate_moves().is_empty() {
+            return if self.get_player().check {
+                Status::Checkmate(!self.current_player)
+            } else {
+                Status::Stalemate
+            };
+        }
+
+        let repetitions = self.repetitions();
+        if self.insufficient_material() {
+            Status::InsufficientMaterial
+        } else if repetitions >= 5 {
+            Status::FivefoldRepetition
+        } else if self.halfmove_clock >= 150 {
+            Status::SeventyFiveMove
+        } else if repetitions >= 3 {
+            Status::ThreefoldRepetition
+        } else if self.halfmove_clock >= 100 {
+            Status::FiftyMoveRule
+        } else {
+            Status::Ongoing
+        }
+    }
+
+    // The en passant space, only if a pawn is there to capture on it
+    fn en_passant_target(&self) -> Option<usize> {
+        let side = self.current_player;
+        self.en_passant.filter(|space| {
+            [UP_LEFT, UP_RIGHT].into_iter().any(|offset| {
+                self.attack_jump(*space, side, &[Piece::Pawn], offset, side == Side::Black)
+                    .is_some()
+            })
+        })
+    }
+
+    // How many times the current position has been reached, including now
+    // Only positions since the last capture or pawn move can match, so no further back is searched
+    fn repetitions(&self) -> usize {
+        let key = self.key;
+        1 + self
+            .history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock)
+            .filter(|previous| **previous == key)
+            .count()
+    }
+
+    // Neither side can mate with a lone king, a king and minor piece, or kings and bishops all on one colour
+    fn insufficient_material(&self) -> bool {
+        let mut minors = vec![];
+        for player in [&self.white, &self.black] {
+            for (piece, space) in player.pieces.iter() {
+                match piece {
+                    Piece::King => {}
+                    Piece::Knight | Piece::Bishop => minors.push((piece, space)),
+                    _ => return false,
+                }
+            }
+        }
+
+        // Bishops are on light spaces when their file and rank add up to an odd number
+        let colour = |space: usize| ((space >> 4) + (space & 0x07)) % 2;
+        match minors[..] {
+            [] | [_] => true,
+            _ => minors.iter().all(|(piece, space)| {
+                *piece == Piece::Bishop && colour(*space) == colour(minors[0].1)
+            }),
+        }
+    }
+
+    // Generate every legal move for the current player
+    pub fn generate_moves(&self) -> Vec<Move> {
+        self.generate_stage(Stage::All)
+    }
+
+    // Generate the legal moves for the current player that belong to one stage
+    pub fn generate_stage(&self, stage: Stage) -> Vec<Move> {
         let mut moves = vec![];
 
         let player = self.get_player();
+        let restrictions = Restrictions {
+            stage,
+            ..self.restrictions()
+        };
 
         for (piece, position) in player.pieces.iter() {
             match piece {
-                Piece::King => self.generate_king_moves(&mut moves, *position),
-                Piece::Queen => self.generate_queen_moves(&mut moves, *position),
-                Piece::Rook(_) => self.generate_rook_moves(&mut moves, *position),
-                Piece::Knight(_) => self.generate_knight_moves(&mut moves, *position),
-                Piece::Bishop(_) => self.generate_bishop_moves(&mut moves, *position),
-                Piece::Pawn(_) => self.generate_pawn_moves(&mut moves, *position),
+                Piece::King => self.generate_king_moves(&mut moves, position, &restrictions),
+                Piece::Queen => self.generate_queen_moves(&mut moves, position, &restrictions),
+                Piece::Rook => self.generate_rook_moves(&mut moves, position, &restrictions),
+                Piece::Knight => self.generate_knight_moves(&mut moves, position, &restrictions),
+                Piece::Bishop => self.generate_bishop_moves(&mut moves, position, &restrictions),
+                Piece::Pawn => self.generate_pawn_moves(&mut moves, position, &restrictions),
             }
         }
 
@@ -311,155 +636,227 @@ impl Game {
     }
 
     // Is the king is check in this position?
+    #[inline(always)]
     fn king_check(&self, side: Side, position: usize) -> bool {
-        // Check knight attacks
-        KNIGHT_MOVES.iter().fold(false, |val, offset| {
-            self.king_check_inner_jump(side, position, discriminant(&Piece::Knight(false)), val, offset)
-        }) ||
-        // // Check rook attacks
-        [UP, RIGHT].iter().fold(false, |val, offset| {
-            self.king_check_inner(side, position, discriminant(&Piece::Rook(false)), val, offset)
-        }) ||
-        // Check bishop attacks
-        [UP_LEFT, UP_RIGHT].iter().fold(false, |val, offset| {
-            self.king_check_inner(side, position, discriminant(&Piece::Bishop(false)), val, offset)
-        }) ||
-        // Check queen attacks
-        [UP, RIGHT, UP_LEFT, UP_RIGHT]
-            .iter()
-            .fold(false, |val, offset| {
-                self.king_check_inner(side, position, discriminant(&Piece::Queen), val, offset)
-            }) ||
-        // Check pawn attacks
-        [UP_LEFT, UP_RIGHT].iter().fold(false, |val, offset| {
-            // Detect black attacking pawns - which from above
-            if side == Side::White {
-                if let Some(Space {
-                    piece: Piece::Pawn(_),
-                    side: Side::Black,
-                }) = self.board[position + offset]
-                {
-                    return true;
-                };
+        self.is_attacked(position, !side)
+    }
 
-            false || val
+    // Is the space attacked by any piece of the given side?
+    pub fn is_attacked(&self, space: usize, by: Side) -> bool {
+        self.visit_attackers(space, by, 0x88, |_| true)
+    }
 
-            // Detect white attacking pawns - which attack from below
-            } else {
-                if let Some(attack) = position.checked_sub(*offset) {
-                    if let Some(Space {
-                        piece: Piece::Pawn(_),
-                        side: Side::White,
-                    }) = self.board[attack]
-                    {
-                        return true;
-                    }
-                }
+    // Is the space attacked, treating the ignored space as empty?
+    fn is_attacked_through(&self, space: usize, by: Side, ignore: usize) -> bool {
+        self.visit_attackers(space, by, ignore, |_| true)
+    }
 
-                false || val
-            }
-        })
+    // The spaces of every piece of the given side attacking the space, in no particular order
+    pub fn attackers(&self, space: usize, by: Side) -> Vec<usize> {
+        let mut attackers = vec![];
+        self.visit_attackers(space, by, 0x88, |attack| {
+            attackers.push(attack);
+            false
+        });
+
+        attackers
     }
 
-    fn king_check_inner_jump(
+    // Call visit with each attacker of the space, stopping early if it returns true
+    // Sliding pieces see through the ignored space, pass an off board space to ignore nothing
+    fn visit_attackers(
         &self,
-        side: Side,
-        position: usize,
-        attack_piece: Discriminant<Piece>,
-        val: bool,
-        offset: &usize,
+        space: usize,
+        by: Side,
+        ignore: usize,
+        mut visit: impl FnMut(usize) -> bool,
     ) -> bool {
-        if position + offset & 0x88 == 0 {
-            if let Some(space) = self.board[position + offset] {
-                if space.side != side && discriminant(&space.piece) == attack_piece {
-                    return true;
+        for up in [true, false] {
+            for offset in KNIGHT_MOVES {
+                if let Some(attack) = self.attack_jump(space, by, &[Piece::Knight], offset, up) {
+                    if visit(attack) {
+                        return true;
+                    }
                 }
             }
-        }
-        if let Some(attack) = position.checked_sub(*offset) {
-            if attack & 0x88 == 0 {
-                if let Some(space) = self.board[attack] {
-                    if space.side != side && discriminant(&space.piece) == attack_piece {
+            for offset in [UP_RIGHT, UP, UP_LEFT, RIGHT] {
+                if let Some(attack) = self.attack_jump(space, by, &[Piece::King], offset, up) {
+                    if visit(attack) {
                         return true;
-                    } else {
-                        return false || val;
                     }
                 }
             }
         }
 
-        false || val
+        // What a slider on the space would attack includes the first piece along each line, and that piece
+        // attacks the space if it slides the same way
+        let occupied = if ignore & 0x88 == 0 {
+            self.occupied & !(1 << to_square(ignore))
+        } else {
+            self.occupied
+        };
+        for (attacks, sliders) in [
+            (
+                rook_attacks(to_square(space), occupied),
+                [Piece::Rook, Piece::Queen],
+            ),
+            (
+                bishop_attacks(to_square(space), occupied),
+                [Piece::Bishop, Piece::Queen],
+            ),
+        ] {
+            for attack in squares(attacks & occupied).map(to_space) {
+                match self.board[attack] {
+                    Some(attacker) if attacker.side == by && sliders.contains(&attacker.piece) => {
+                        if visit(attack) {
+                            return true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Pawns attack away from their own side, so look back towards it for them
+        for offset in [UP_LEFT, UP_RIGHT] {
+            if let Some(attack) =
+                self.attack_jump(space, by, &[Piece::Pawn], offset, by == Side::Black)
+            {
+                if visit(attack) {
+                    return true;
+                }
+            }
+        }
+
+        false
     }
 
-    fn king_check_inner(
+    // The space one offset away, up or down the board, if it is on the board
+    #[inline(always)]
+    fn step(space: usize, offset: usize, up: bool) -> Option<usize> {
+        let next = if up {
+            space + offset
+        } else {
+            space.checked_sub(offset)?
+        };
+        (next & 0x88 == 0).then_some(next)
+    }
+
+    // The attacking piece one jump away, if there is one
+    fn attack_jump(
         &self,
-        side: Side,
-        position: usize,
-        attack_piece: Discriminant<Piece>,
-        val: bool,
-        offset: &usize,
-    ) -> bool {
-        let mut attack = position;
-        loop {
-            attack += offset;
-            if attack & 0x88 == 0 {
-                if let Some(space) = self.board[attack] {
-                    if space.side != side && discriminant(&space.piece) == attack_piece {
-                        return true;
-                    } else {
-                        break;
-                    }
-                }
-            } else {
-                break;
+        space: usize,
+        by: Side,
+        attack_pieces: &[Piece],
+        offset: usize,
+        up: bool,
+    ) -> Option<usize> {
+        let attack = Game::step(space, offset, up)?;
+        match self.board[attack] {
+            Some(attacker) if attacker.side == by && attack_pieces.contains(&attacker.piece) => {
+                Some(attack)
             }
+            _ => None,
         }
+    }
 
-        attack = position;
-        loop {
-            attack = match attack.checked_sub(*offset) {
-                Some(new) => new,
-                None => 0x88,
-            };
-            if attack & 0x88 == 0 {
-                if let Some(space) = self.board[attack] {
-                    if space.side != side && discriminant(&space.piece) == attack_piece {
-                        return true;
-                    } else {
-                        break;
+    // Find what the current player's checks and pins leave their pieces free to do
+    fn restrictions(&self) -> Restrictions {
+        let side = self.current_player;
+        let king = self.get_player().pieces.king();
+        let checkers = self.attackers(king, !side);
+        let mut restrictions = Restrictions {
+            // Capturing is the only answer to a knight or pawn, and nothing but the king can answer two checks
+            evasions: match checkers[..] {
+                [] => !0,
+                [checker] => 1 << checker,
+                _ => 0,
+            },
+            pins: vec![],
+            stage: Stage::All,
+        };
+
+        // Look along each line out from the king, and again through the first of our pieces on it
+        // An enemy slider seen either way is checking the king or pinning the one piece of ours in the way
+        let (king_square, occupied) = (to_square(king), self.occupied);
+        for (attacks, sliders) in [
+            (
+                rook_attacks as fn(usize, u64) -> u64,
+                [Piece::Rook, Piece::Queen],
+            ),
+            (bishop_attacks, [Piece::Bishop, Piece::Queen]),
+        ] {
+            let ours = squares(attacks(king_square, occupied) & occupied)
+                .filter(|square| self.board[to_space(*square)].unwrap().side == side)
+                .fold(0, |ours, square| ours | 1 << square);
+            for square in squares(attacks(king_square, occupied & !ours) & occupied & !ours) {
+                match self.board[to_space(square)] {
+                    Some(piece) if piece.side != side && sliders.contains(&piece.piece) => {
+                        // Both lookups stopped by the other piece only meet on the spaces between them
+                        let between =
+                            attacks(king_square, 1 << square) & attacks(square, 1 << king_square);
+                        let ray = squares(between | 1 << square)
+                            .fold(0u128, |ray, square| ray | 1 << to_space(square));
+                        match squares(between & ours).next() {
+                            Some(pinned) => restrictions.pins.push((to_space(pinned), ray)),
+                            None if checkers.len() == 1 => restrictions.evasions = ray,
+                            None => {}
+                        }
                     }
+                    _ => {}
                 }
-            } else {
-                break;
             }
         }
 
-        false || val
+        restrictions
     }
 
     // Individual peice move functions to ease testing
     #[inline(always)]
-    fn generate_king_moves(&self, moves: &mut Vec<Game>, src: usize) {
+    fn generate_king_moves(&self, moves: &mut Vec<Move>, src: usize, restrictions: &Restrictions) {
         [UP_RIGHT, UP, UP_LEFT, RIGHT].iter().for_each(|offset| {
-            self.make_jump_move(moves, src, src + offset);
+            self.make_jump_move(moves, src, src + offset, restrictions);
             match src.checked_sub(*offset) {
-                Some(dest) => self.make_jump_move(moves, src, dest),
+                Some(dest) => self.make_jump_move(moves, src, dest, restrictions),
                 None => {}
             }
-        })
+        });
+
+        // Castling rights are only held while the king and rook are unmoved, so they must be on their home squares
+        // Wherever they start, the king lands on the g or c file and the rook beside it on the f or d file,
+        // and every space either passes over has to be empty apart from the two of them
+        // The king can't castle out of or through check, landing in check is caught by push_legal
+        let player = self.get_player();
+        if !player.check {
+            let home = src & 0x70;
+            for (allowed, rook, king_dest, rook_dest) in [
+                (player.castle_kingside, player.rook_files[0], 6, 5),
+                (player.castle_queenside, player.rook_files[1], 2, 3),
+            ] {
+                let (rook, king_dest, rook_dest) =
+                    (home + rook, home + king_dest, home + rook_dest);
+                let span = |a: usize, b: usize| a.min(b)..=a.max(b);
+                if allowed
+                    && span(src, king_dest)
+                        .chain(span(rook, rook_dest))
+                        .all(|space| space == src || space == rook || self.board[space].is_none())
+                    && span(src, king_dest)
+                        .all(|space| space == src || !self.king_check(self.current_player, space))
+                {
+                    self.push_castle(moves, src, king_dest, restrictions);
+                }
+            }
+        }
     }
 
     #[inline(always)]
-    fn generate_pawn_moves(&self, moves: &mut Vec<Game>, src: usize) {
-        // TODO: En passant
+    fn generate_pawn_moves(&self, moves: &mut Vec<Move>, src: usize, restrictions: &Restrictions) {
         match self.current_player {
             Side::White => {
                 let dest = src + UP;
                 if dest & 0x88 == 0 {
                     if let None = self.board[dest] {
-                        if let Some(m) = self.make_move(src, dest) {
-                            moves.push(m)
-                        }
+                        self.make_pawn_move(moves, src, dest, restrictions);
                     }
                 }
                 // If we're on the starting space, generate the two space move
@@ -467,9 +864,7 @@ impl Game {
                 if src >= 0x10 && src <= 0x17 && self.board[src + 0x10] == None {
                     let dest = src + UP + UP;
                     if let None = self.board[dest] {
-                        if let Some(m) = self.make_move(src, dest) {
-                            moves.push(m)
-                        }
+                        self.make_pawn_move(moves, src, dest, restrictions);
                     }
                 }
                 let dest = src + UP_RIGHT;
@@ -478,9 +873,7 @@ impl Game {
                         side: Side::Black, ..
                     }) = self.board[dest]
                     {
-                        if let Some(m) = self.make_move(src, dest) {
-                            moves.push(m)
-                        }
+                        self.make_pawn_move(moves, src, dest, restrictions);
                     }
                 }
                 let dest = src + UP_LEFT;
@@ -489,9 +882,13 @@ impl Game {
                         side: Side::Black, ..
                     }) = self.board[dest]
                     {
-                        if let Some(m) = self.make_move(src, dest) {
-                            moves.push(m)
-                        }
+                        self.make_pawn_move(moves, src, dest, restrictions);
+                    }
+                }
+                // The en passant space is always empty, so it needs checking separately
+                if let Some(dest) = self.en_passant {
+                    if dest == src + UP_RIGHT || dest == src + UP_LEFT {
+                        self.make_pawn_move(moves, src, dest, restrictions);
                     }
                 }
             }
@@ -500,9 +897,7 @@ impl Game {
                     Some(dest) => {
                         if dest & 0x88 == 0 {
                             if let None = self.board[dest] {
-                                if let Some(m) = self.make_move(src, dest) {
-                                    moves.push(m)
-                                }
+                                self.make_pawn_move(moves, src, dest, restrictions);
                             }
                         }
                     }
@@ -513,9 +908,7 @@ impl Game {
                 if src >= 0x60 && src <= 0x67 && self.board[src - 0x10] == None {
                     let dest = src - UP - UP;
                     if let None = self.board[dest] {
-                        if let Some(m) = self.make_move(src, dest) {
-                            moves.push(m)
-                        }
+                        self.make_pawn_move(moves, src, dest, restrictions);
                     }
                 }
                 match src.checked_sub(UP_RIGHT) {
@@ -525,9 +918,7 @@ impl Game {
                                 side: Side::White, ..
                             }) = self.board[dest]
                             {
-                                if let Some(m) = self.make_move(src, dest) {
-                                    moves.push(m)
-                                }
+                                self.make_pawn_move(moves, src, dest, restrictions);
                             }
                         }
                     }
@@ -540,320 +931,636 @@ impl Game {
                                 side: Side::White, ..
                             }) = self.board[dest]
                             {
-                                if let Some(m) = self.make_move(src, dest) {
-                                    moves.push(m)
-                                }
+                                self.make_pawn_move(moves, src, dest, restrictions);
                             }
                         }
                     }
                     None => {}
                 }
+                // The en passant space is always empty, so it needs checking separately
+                if let Some(dest) = self.en_passant {
+                    if Some(dest) == src.checked_sub(UP_RIGHT)
+                        || Some(dest) == src.checked_sub(UP_LEFT)
+                    {
+                        self.make_pawn_move(moves, src, dest, restrictions);
+                    }
+                }
             }
         }
     }
 
     #[inline(always)]
-    fn generate_knight_moves(&self, moves: &mut Vec<Game>, src: usize) {
+    fn generate_knight_moves(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        restrictions: &Restrictions,
+    ) {
         KNIGHT_MOVES.iter().for_each(|offset| {
-            self.make_jump_move(moves, src, src + offset);
+            self.make_jump_move(moves, src, src + offset, restrictions);
             match src.checked_sub(*offset) {
-                Some(dest) => self.make_jump_move(moves, src, dest),
+                Some(dest) => self.make_jump_move(moves, src, dest, restrictions),
                 None => {}
             }
         })
     }
 
     #[inline(always)]
-    fn generate_queen_moves(&self, moves: &mut Vec<Game>, src: usize) {
+    fn generate_queen_moves(&self, moves: &mut Vec<Move>, src: usize, restrictions: &Restrictions) {
         // Queen moves as the union of rook and bishop
-        self.generate_rook_moves(moves, src);
-        self.generate_bishop_moves(moves, src);
+        self.generate_rook_moves(moves, src, restrictions);
+        self.generate_bishop_moves(moves, src, restrictions);
     }
 
     #[inline(always)]
-    fn generate_bishop_moves(&self, moves: &mut Vec<Game>, src: usize) {
-        self.make_sliding_moves(moves, src, |i| i + UP_RIGHT);
-        self.make_sliding_moves(moves, src, |i| i + UP_LEFT);
-        self.make_sliding_moves(moves, src, |i| {
-            // Invert UP_RIGHT becomes DOWN_LEFT
-            // If we underflow we're off the bottom, so set to a know fail value
-            match i.checked_sub(UP_RIGHT) {
-                Some(i) => i,
-                None => 0x88,
-            }
-        });
-        self.make_sliding_moves(moves, src, |i| {
-            // Invert UP_LEFT becomes DOWN_RIGHT
-            // If we underflow we're off the bottom, so set to a know fail value
-            match i.checked_sub(UP_LEFT) {
-                Some(i) => i,
-                None => 0x88,
-            }
-        });
+    fn generate_bishop_moves(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        restrictions: &Restrictions,
+    ) {
+        self.make_sliding_moves(moves, src, restrictions, bishop_attacks);
     }
 
     #[inline(always)]
-    fn generate_rook_moves(&self, moves: &mut Vec<Game>, src: usize) {
-        self.make_sliding_moves(moves, src, |i| i + RIGHT);
-        self.make_sliding_moves(moves, src, |i| i + UP);
-        self.make_sliding_moves(moves, src, |i| {
-            // Invert UP becomes DOWN
-            // If we underflow we're off the bottom, so set to a know fail value
-            match i.checked_sub(UP) {
-                Some(i) => i,
-                None => 0x88,
-            }
-        });
-        self.make_sliding_moves(moves, src, |i| {
-            // Invert RIGHT becomes LEFT
-            // If we underflow we're off the bottom, so set to a know fail value
-            match i.checked_sub(RIGHT) {
-                Some(i) => i,
-                None => 0x88,
-            }
-        });
+    fn generate_rook_moves(&self, moves: &mut Vec<Move>, src: usize, restrictions: &Restrictions) {
+        self.make_sliding_moves(moves, src, restrictions, rook_attacks);
     }
 
-    fn make_sliding_moves(&self, moves: &mut Vec<Game>, src: usize, src_exp: fn(usize) -> usize) {
-        let mut dest = src;
-        loop {
-            dest = src_exp(dest);
-            if dest & 0x88 == 0 {
-                match self.board[dest] {
-                    Some(target) => {
-                        if target.side != self.current_player {
-                            if let Some(m) = self.make_move(src, dest) {
-                                moves.push(m)
-                            }
-                        }
-                        break;
-                    }
-                    None => {
-                        if let Some(m) = self.make_move(src, dest) {
-                            moves.push(m)
-                        }
-                    }
-                }
-            } else {
-                break;
-            }
+    // Look up every space the piece reaches, the first piece on each line included
+    fn make_sliding_moves(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        restrictions: &Restrictions,
+        attacks: fn(usize, u64) -> u64,
+    ) {
+        for dest in squares(attacks(to_square(src), self.occupied)).map(to_space) {
+            self.make_jump_move(moves, src, dest, restrictions);
         }
     }
 
-    fn make_jump_move(&self, moves: &mut Vec<Game>, src: usize, dest: usize) {
+    fn make_jump_move(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        dest: usize,
+        restrictions: &Restrictions,
+    ) {
         if dest & 0x88 == 0 {
             match self.board[dest] {
                 Some(target) => {
                     if target.side != self.current_player {
-                        if let Some(m) = self.make_move(src, dest) {
-                            moves.push(m)
-                        }
+                        self.push_move(moves, src, dest, None, restrictions);
                     }
                 }
                 None => {
-                    if let Some(m) = self.make_move(src, dest) {
-                        moves.push(m)
-                    }
+                    self.push_move(moves, src, dest, None, restrictions);
                 }
             }
         }
     }
 
+    fn make_pawn_move(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        dest: usize,
+        restrictions: &Restrictions,
+    ) {
+        // Pawns reaching the last rank must promote, to any of these
+        if !(0x08..0x70).contains(&dest) {
+            for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                self.push_move(moves, src, dest, Some(piece), restrictions);
+            }
+        } else {
+            self.push_move(moves, src, dest, None, restrictions);
+        }
+    }
+
+    #[inline(always)]
+    fn push_move(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        dest: usize,
+        promotion: Option<Piece>,
+        restrictions: &Restrictions,
+    ) {
+        let mut flags = 0;
+        if self.board[dest].is_some() {
+            flags |= CAPTURE;
+        }
+        match self.board[src] {
+            Some(Space {
+                piece: Piece::Pawn, ..
+            }) if Some(dest) == self.en_passant => flags |= CAPTURE | EN_PASSANT,
+            Some(Space {
+                piece: Piece::Pawn, ..
+            }) if dest == src + UP + UP || dest + UP + UP == src => flags |= DOUBLE_PUSH,
+            _ => {}
+        }
+        let m = Move {
+            from: src as u8,
+            to: dest as u8,
+            promotion,
+            flags,
+        };
+        self.push_legal(moves, m, restrictions);
+    }
+
+    // Castling can put the king on its own rook's space, so it is built here rather than by push_move
+    fn push_castle(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        dest: usize,
+        restrictions: &Restrictions,
+    ) {
+        let m = Move {
+            from: src as u8,
+            to: dest as u8,
+            promotion: None,
+            flags: CASTLE,
+        };
+        self.push_legal(moves, m, restrictions);
+    }
+
     #[inline(always)]
-    fn make_move(&self, src: usize, dest: usize) -> Option<Game> {
+    fn push_legal(&self, moves: &mut Vec<Move>, m: Move, restrictions: &Restrictions) {
+        let (src, dest) = (m.from(), m.to());
+        let piece = self.board[src].unwrap().piece;
+
+        // Skip moves from other stages before the more expensive tests
+        let quiet = !m.is_capture() && m.promotion().is_none();
+        match restrictions.stage {
+            Stage::Captures if quiet => return,
+            Stage::Quiets | Stage::QuietChecks if !quiet => return,
+            _ => {}
+        }
+
+        // Only keep the move if it doesn't leave our king in check
+        let legal = match piece {
+            // En passant takes a piece off a different space, which can uncover the king sideways, and in
+            // Chess960 the castling rook can be all that shields the king's destination, so these rare
+            // cases are simplest checked by trying them
+            _ if m.is_en_passant() || m.is_castle() => {
+                let mut scratch = self.without_history();
+                scratch.apply_move(m);
+                !scratch.get_side(self.current_player).check
+            }
+            // The king can't stay on the line of a slider checking it, so look through the space it leaves
+            Piece::King => !self.is_attacked_through(dest, !self.current_player, src),
+            _ => restrictions.allows(src, dest),
+        };
+        if legal && (restrictions.stage != Stage::QuietChecks || self.gives_check(m)) {
+            moves.push(m)
+        }
+    }
+
+    // Does the move put the opponent in check?
+    fn gives_check(&self, m: Move) -> bool {
+        let mut scratch = self.without_history();
+        scratch.apply_move(m);
+        scratch.get_side(!self.current_player).check
+    }
+
+    // A copy of the position to try moves on, leaving out the history so nothing is allocated
+    fn without_history(&self) -> Game {
+        Game {
+            board: self.board,
+            occupied: self.occupied,
+            current_player: self.current_player,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            white: self.white.clone(),
+            black: self.black.clone(),
+            history: vec![],
+            key: self.key,
+        }
+    }
+
+    // Apply a move, giving the resulting game or None if it isn't one of the legal moves
+    pub fn make_move(&self, m: Move) -> Option<Game> {
+        let m = m.find_in(self.generate_moves())?;
         let mut new_board = self.clone();
+        new_board.do_move(m);
 
-        match self.current_player {
-            Side::White => {
-                // Update piece hashmaps
-                new_board
-                    .white
-                    .pieces
-                    .insert(new_board.board[src].unwrap().piece, dest);
-                if let Some(space) = new_board.board[dest] {
-                    new_board.black.pieces.remove(&space.piece);
-                }
+        Some(new_board)
+    }
 
-                // Update board array
-                new_board.board[dest] = new_board.board[src];
-                new_board.board[src] = None;
+    // Apply a move in place, returning what is needed to take it back with undo_move
+    // The move is not checked for legality, so this should be given moves from generate_moves
+    pub fn do_move(&mut self, m: Move) -> Undo {
+        self.history.push(self.key);
+        self.apply_move(m)
+    }
 
-                // Did we check ourselves
-                if new_board.king_check(Side::White, new_board.white.pieces[&Piece::King]) {
-                    None
-                } else {
-                    // Did we check the opponent
-                    new_board.black.check =
-                        new_board.king_check(Side::Black, new_board.black.pieces[&Piece::King]);
+    // Move the pieces and update the game state, without recording the position in the history
+    fn apply_move(&mut self, m: Move) -> Undo {
+        let (src, dest, promotion) = (m.from(), m.to(), m.promotion());
+        let side = self.current_player;
+        let piece = self.board[src].unwrap().piece;
 
-                    new_board.current_player = !new_board.current_player;
-                    Some(new_board)
-                }
+        // In Chess960 the king can land where the rook started, so lift the rook off until the king has moved
+        let castle = m.is_castle().then(|| self.castling_rook(side, dest));
+        if let Some((rook_src, _)) = castle {
+            self.set_space(rook_src, None);
+        }
+
+        let undo = Undo {
+            // A Chess960 king can castle without moving, so its own piece is never captured
+            captured: self.board[dest]
+                .filter(|space| space.side != side)
+                .map(|space| space.piece),
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            castling: [
+                self.white.castle_kingside,
+                self.white.castle_queenside,
+                self.black.castle_kingside,
+                self.black.castle_queenside,
+            ],
+            check: [self.white.check, self.black.check],
+        };
+
+        // Take the castling rights and en passant out of the key, they go back in once they're updated
+        self.key ^= self.castling_key() ^ self.en_passant_key() ^ Game::side_key();
+
+        // Update piece lists
+        if let Some(captured) = undo.captured {
+            self.get_side_mut(!side).pieces.remove(captured, dest);
+            self.key ^= Game::piece_key(
+                Space {
+                    piece: captured,
+                    side: !side,
+                },
+                dest,
+            );
+        }
+        match promotion {
+            Some(promotion) => {
+                let player = self.get_side_mut(side);
+                player.pieces.remove(piece, src);
+                player.pieces.add(promotion, dest);
+                self.set_space(
+                    src,
+                    Some(Space {
+                        piece: promotion,
+                        side,
+                    }),
+                );
             }
-            Side::Black => {
-                // Update piece hashmaps
-                new_board
-                    .black
+            None => self.get_side_mut(side).pieces.relocate(piece, src, dest),
+        }
+
+        // Update board array
+        self.key ^= Game::piece_key(Space { piece, side }, src)
+            ^ Game::piece_key(self.board[src].unwrap(), dest);
+        let moved = self.board[src];
+        self.set_space(src, None);
+        self.set_space(dest, moved);
+
+        self.en_passant = None;
+        if piece == Piece::Pawn || undo.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if side == Side::Black {
+            self.fullmove_number += 1;
+        }
+
+        if let Piece::Pawn = piece {
+            // An en passant capture takes the pawn behind the destination space
+            if Some(dest) == undo.en_passant {
+                let captured = Game::en_passant_capture(side, dest);
+                self.get_side_mut(!side)
                     .pieces
-                    .insert(new_board.board[src].unwrap().piece, dest);
-                if let Some(space) = new_board.board[dest] {
-                    new_board.white.pieces.remove(&space.piece);
-                }
+                    .remove(Piece::Pawn, captured);
+                self.key ^= Game::piece_key(self.board[captured].unwrap(), captured);
+                self.set_space(captured, None);
+            }
 
-                // Update board array
-                new_board.board[dest] = new_board.board[src];
-                new_board.board[src] = None;
+            // A double move leaves the skipped space open to en passant
+            if dest == src + UP + UP {
+                self.en_passant = Some(src + UP);
+            } else if dest + UP + UP == src {
+                self.en_passant = Some(dest + UP);
+            }
+        }
 
-                // Did we check ourselves
-                if new_board.king_check(Side::Black, new_board.black.pieces[&Piece::King]) {
-                    None
-                } else {
-                    // Did we check the opponent
-                    new_board.white.check =
-                        new_board.king_check(Side::White, new_board.white.pieces[&Piece::King]);
+        // Put the rook down beside the king
+        if let Some((rook_src, rook_dest)) = castle {
+            self.place_rook(side, rook_src, rook_dest);
+        }
 
-                    new_board.current_player = !new_board.current_player;
-                    Some(new_board)
-                }
+        // Castling rights are lost once the king moves, or anything moves from or is captured on a rook's starting space
+        if piece == Piece::King {
+            let player = self.get_side_mut(side);
+            player.castle_kingside = false;
+            player.castle_queenside = false;
+        }
+        for (side, home) in [(Side::White, 0x00), (Side::Black, 0x70)] {
+            let player = self.get_side_mut(side);
+            let [kingside, queenside] = player.rook_files.map(|file| home + file);
+            if src == kingside || dest == kingside {
+                player.castle_kingside = false;
+            }
+            if src == queenside || dest == queenside {
+                player.castle_queenside = false;
             }
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Update both check flags, the mover's is only set if the move was illegal
+        for side in [side, !side] {
+            self.get_side_mut(side).check =
+                self.king_check(side, self.get_side(side).pieces.king());
+        }
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    struct Level {
-        moves: usize,
-        captures: usize,
-        checks: usize,
+        self.current_player = !side;
+        self.key ^= self.castling_key() ^ self.en_passant_key();
+        undo
     }
 
-    fn perft_ply<const N: usize>(levels: &mut [Level; N], game: &Game, depth: usize) {
-        if depth == 0 {
-            return;
+    // Take back a move made by do_move, restoring the game to exactly how it was before
+    pub fn undo_move(&mut self, m: Move, undo: Undo) {
+        let (src, dest, promotion) = (m.from(), m.to(), m.promotion());
+        let side = !self.current_player;
+        let piece = self.board[dest].unwrap().piece;
+
+        // Lift the rook off, it goes back once the king is out of the way
+        let castle = m.is_castle().then(|| self.castling_rook(side, dest));
+        if let Some((_, rook_dest)) = castle {
+            self.set_space(rook_dest, None);
         }
 
-        let moves = game.generate_ply();
-        levels[levels.len() - depth].moves += moves.len();
-
-        for new_game in moves {
-            if new_game.black.check || new_game.white.check {
-                println!(
-                    "{} {}",
-                    new_game,
-                    if new_game.black.check {
-                        "black"
-                    } else {
-                        "white"
-                    }
+        // Move the piece back, turning it back into a pawn if it was promoted
+        match promotion {
+            Some(promotion) => {
+                let player = self.get_side_mut(side);
+                player.pieces.remove(promotion, dest);
+                player.pieces.add(Piece::Pawn, src);
+                self.set_space(
+                    src,
+                    Some(Space {
+                        piece: Piece::Pawn,
+                        side,
+                    }),
                 );
-                levels[levels.len() - depth].checks += 1;
+                self.set_space(dest, None);
             }
-            if new_game.black.pieces.len() + new_game.white.pieces.len()
-                != game.black.pieces.len() + game.white.pieces.len()
-            {
-                levels[levels.len() - depth].captures += 1;
-
-                // Sanity check
-                if (game.black.pieces.len() + game.white.pieces.len())
-                    - (new_game.black.pieces.len() + new_game.white.pieces.len())
-                    != 1
-                {
-                    panic!("invalid capture");
-                }
+            None => {
+                self.get_side_mut(side).pieces.relocate(piece, dest, src);
+                let moved = self.board[dest];
+                self.set_space(dest, None);
+                self.set_space(src, moved);
             }
+        }
+        if let Some((rook_src, rook_dest)) = castle {
+            self.place_rook(side, rook_dest, rook_src);
+        }
 
-            perft_ply(levels, &new_game, depth - 1);
+        // Return whatever was captured
+        if let Some(captured) = undo.captured {
+            self.get_side_mut(!side).pieces.add(captured, dest);
+            self.set_space(
+                dest,
+                Some(Space {
+                    piece: captured,
+                    side: !side,
+                }),
+            );
+        } else if piece == Piece::Pawn && Some(dest) == undo.en_passant {
+            let captured = Game::en_passant_capture(side, dest);
+            self.get_side_mut(!side).pieces.add(Piece::Pawn, captured);
+            self.set_space(
+                captured,
+                Some(Space {
+                    piece: Piece::Pawn,
+                    side: !side,
+                }),
+            );
         }
+
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        if side == Side::Black {
+            self.fullmove_number -= 1;
+        }
+        [
+            self.white.castle_kingside,
+            self.white.castle_queenside,
+            self.black.castle_kingside,
+            self.black.castle_queenside,
+        ] = undo.castling;
+        [self.white.check, self.black.check] = undo.check;
+        self.key = self.history.pop().unwrap();
+
+        self.current_player = side;
     }
 
-    #[test]
-    pub fn perft_test() {
-        let game = Game::new();
+    // The space holding the pawn taken by an en passant capture landing on dest
+    fn en_passant_capture(side: Side, dest: usize) -> usize {
+        match side {
+            Side::White => dest - UP,
+            Side::Black => dest + UP,
+        }
+    }
 
-        let moves = game.generate_ply();
-        let mut output = HashMap::<&str, usize>::new();
-
-        const DEPTH: usize = 4;
-
-        for new_move in moves {
-            let mut levels = [Level {
-                moves: 0,
-                captures: 0,
-                checks: 0,
-            }; DEPTH];
-            perft_ply(&mut levels, &new_move, DEPTH - 1);
-            match diff_board(&game, &new_move) {
-                (0x20, Piece::Pawn(0)) => output.insert("a2a3", levels[DEPTH - 1].moves),
-                (0x21, _) => output.insert("b2b3", levels[DEPTH - 1].moves),
-                (0x22, Piece::Pawn(2)) => output.insert("c2c3", levels[DEPTH - 1].moves),
-                (0x23, _) => output.insert("d2d3", levels[DEPTH - 1].moves),
-                (0x24, _) => output.insert("e2e3", levels[DEPTH - 1].moves),
-                (0x25, Piece::Pawn(5)) => output.insert("f2f3", levels[DEPTH - 1].moves),
-                (0x26, _) => output.insert("g2g3", levels[DEPTH - 1].moves),
-                (0x27, Piece::Pawn(7)) => output.insert("h2h3", levels[DEPTH - 1].moves),
-                (0x30, _) => output.insert("a2a4", levels[DEPTH - 1].moves),
-                (0x31, _) => output.insert("b2b4", levels[DEPTH - 1].moves),
-                (0x32, _) => output.insert("c2c4", levels[DEPTH - 1].moves),
-                (0x33, _) => output.insert("d2d4", levels[DEPTH - 1].moves),
-                (0x34, _) => output.insert("e2e4", levels[DEPTH - 1].moves),
-                (0x35, _) => output.insert("f2f4", levels[DEPTH - 1].moves),
-                (0x36, _) => output.insert("g2g4", levels[DEPTH - 1].moves),
-                (0x37, _) => output.insert("h2h4", levels[DEPTH - 1].moves),
-                (0x20, _) => output.insert("b1a3", levels[DEPTH - 1].moves),
-                (0x22, _) => output.insert("b1c3", levels[DEPTH - 1].moves),
-                (0x25, _) => output.insert("g1f3", levels[DEPTH - 1].moves),
-                (0x27, _) => output.insert("g1h3", levels[DEPTH - 1].moves),
-                _ => panic!("unexpected move"),
-            };
+    // The spaces the rook castles from and to, for a king castling onto dest
+    fn castling_rook(&self, side: Side, dest: usize) -> (usize, usize) {
+        let home = dest & 0x70;
+        let rook_files = self.get_side(side).rook_files;
+        if dest & 0x07 == 6 {
+            (home + rook_files[0], home + 5)
+        } else {
+            (home + rook_files[1], home + 3)
         }
+    }
 
-        for (k, v) in output {
-            if v != match k {
-                "a2a3" => 8457,
-                "b2b3" => 9345,
-                "c2c3" => 9272,
-                "d2d3" => 11959,
-                "e2e3" => 13134,
-                "f2f3" => 8457,
-                "g2g3" => 9345,
-                "h2h3" => 8457,
-                "a2a4" => 9329,
-                "b2b4" => 9332,
-                "c2c4" => 9744,
-                "d2d4" => 12435,
-                "e2e4" => 13160,
-                "f2f4" => 8929,
-                "g2g4" => 9328,
-                "h2h4" => 9329,
-                "b1a3" => 8885,
-                "b1c3" => 9755,
-                "g1f3" => 9748,
-                "g1h3" => 8881,
-                _ => panic!("wrong symbol"),
-            } {
-                println!("{}: {}", k, v);
-                assert!(false);
-            }
+    // Move a castling rook that has already been lifted off the board
+    fn place_rook(&mut self, side: Side, src: usize, dest: usize) {
+        let rook = Space {
+            piece: Piece::Rook,
+            side,
+        };
+        self.get_side_mut(side)
+            .pieces
+            .relocate(Piece::Rook, src, dest);
+        self.key ^= Game::piece_key(rook, src) ^ Game::piece_key(rook, dest);
+        self.set_space(dest, Some(rook));
+    }
+
+    // Which squares are occupied on a board, numbered as the bitboards do
+    fn occupancy(board: &[Option<Space>; 128]) -> u64 {
+        (0..64)
+            .filter(|square| board[to_space(*square)].is_some())
+            .fold(0, |occupied, square| occupied | 1 << square)
+    }
+
+    // Put a piece on a space or clear it, keeping the occupied squares in step with the board
+    fn set_space(&mut self, space: usize, contents: Option<Space>) {
+        let square = 1 << to_square(space);
+        match contents {
+            Some(_) => self.occupied |= square,
+            None => self.occupied &= !square,
         }
+        self.board[space] = contents;
     }
+}
 
-    fn diff_board(a: &Game, b: &Game) -> (usize, Piece) {
-        for (piece, space) in a.white.pieces.iter() {
-            if b.white.pieces[piece] != *space {
-                return (b.white.pieces[piece], piece.clone());
-            }
+impl Position for Game {
+    fn from_fen(raw_game: String) -> Result<Self, FenError> {
+        Game::from_fen(raw_game)
+    }
+
+    fn to_fen(&self) -> String {
+        self.to_fen()
+    }
+
+    fn current_player(&self) -> Side {
+        self.current_player
+    }
+
+    fn in_check(&self) -> bool {
+        self.get_player().check
+    }
+
+    fn is_attacked(&self, space: usize, by: Side) -> bool {
+        self.is_attacked(space, by)
+    }
+
+    fn attackers(&self, space: usize, by: Side) -> Vec<usize> {
+        self.attackers(space, by)
+    }
+
+    fn checkers(&self) -> Vec<usize> {
+        self.attackers(self.get_player().pieces.king(), !self.current_player)
+    }
+
+    fn generate_moves(&self) -> Vec<Move> {
+        self.generate_moves()
+    }
+
+    fn make_move(&self, m: Move) -> Option<Self> {
+        self.make_move(m)
+    }
+
+    fn do_move(&mut self, m: Move) -> Undo {
+        self.do_move(m)
+    }
+
+    fn undo_move(&mut self, m: Move, undo: Undo) {
+        self.undo_move(m, undo)
+    }
+}
+
+// Positions for the tests that run over every legal move, between them covering castling, en passant,
+// promotions, checks and Chess960
+#[cfg(test)]
+const TEST_POSITIONS: [&str; 7] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r3k2r/8/8/8/4Pp2/8/8/R3K2R b Kq e3 3 42",
+    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+];
+
+#[cfg(test)]
+fn test_game(fen: &str) -> Game {
+    Game::from_fen(fen.to_string()).unwrap()
+}
+
+// Every legal move in the test positions has to come back unchanged once written out and parsed again
+#[cfg(test)]
+fn assert_moves_round_trip<E: std::fmt::Debug + PartialEq>(
+    write: impl Fn(&Game, Move) -> String,
+    parse: impl Fn(&Game, &str) -> Result<Move, E>,
+) {
+    for fen in TEST_POSITIONS {
+        let game = test_game(fen);
+        for m in game.generate_moves() {
+            assert_eq!(Ok(m), parse(&game, &write(&game, m)), "{}", fen);
         }
-        for (piece, space) in a.black.pieces.iter() {
-            if b.black.pieces[piece] != *space {
-                return (b.white.pieces[piece], piece.clone());
-            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitboard::BitboardGame;
+    use perft::PerftStats;
+    use std::collections::HashSet;
+
+    // A row of a published perft table: nodes, captures, en passants, castles, promotions, checks,
+    // discovered checks, double checks and checkmates
+    fn stats(row: [u64; 9]) -> PerftStats {
+        let [nodes, captures, en_passants, castles, promotions, checks, discovered_checks, double_checks, checkmates] =
+            row;
+        PerftStats {
+            nodes,
+            captures,
+            en_passants,
+            castles,
+            promotions,
+            checks,
+            discovered_checks,
+            double_checks,
+            checkmates,
         }
+    }
+
+    impl Game {
+        // Apply any move, legal or not, for setting up positions by moving pieces straight to where they're wanted
+        // None if the move leaves the mover in check
+        fn force_move(&self, m: Move) -> Option<Game> {
+            let side = self.current_player;
+            let mut game = self.clone();
+            game.do_move(m);
+            (!game.get_side(side).check).then_some(game)
+        }
+    }
+
+    // Collect each ply with both board representations, which must agree
+    fn perft_levels<const N: usize>(game: &Game) -> [PerftStats; N] {
+        let levels = game.perft_stats(N);
+        assert_eq!(levels, BitboardGame::from(game).perft_stats(N));
+        levels.try_into().unwrap()
+    }
 
-        panic!("no diff");
+    #[test]
+    pub fn perft_test() {
+        let expected = [
+            ("a2a3", 8457),
+            ("a2a4", 9329),
+            ("b1a3", 8885),
+            ("b1c3", 9755),
+            ("b2b3", 9345),
+            ("b2b4", 9332),
+            ("c2c3", 9272),
+            ("c2c4", 9744),
+            ("d2d3", 11959),
+            ("d2d4", 12435),
+            ("e2e3", 13134),
+            ("e2e4", 13160),
+            ("f2f3", 8457),
+            ("f2f4", 8929),
+            ("g1f3", 9748),
+            ("g1h3", 8881),
+            ("g2g3", 9345),
+            ("g2g4", 9328),
+            ("h2h3", 8457),
+            ("h2h4", 9329),
+        ]
+        .map(|(uci, nodes)| (uci.to_string(), nodes));
+
+        let divide = Game::new().divide(4);
+        assert_eq!(&expected[..], divide.moves(), "\n{}", divide);
+        assert_eq!(197281, divide.nodes());
     }
 
     #[test]
@@ -861,117 +1568,259 @@ mod tests {
     // See https://www.chessprogramming.org/Perft for more details
     pub fn perft() {
         let correct_levels = [
-            Level {
-                moves: 20,
-                captures: 0,
-                checks: 0,
-            },
-            Level {
-                moves: 400,
-                captures: 0,
-                checks: 0,
-            },
-            Level {
-                moves: 8902,
-                captures: 34,
-                checks: 12,
-            },
-            Level {
-                moves: 197281,
-                captures: 1576,
-                checks: 469,
-            },
+            stats([20, 0, 0, 0, 0, 0, 0, 0, 0]),
+            stats([400, 0, 0, 0, 0, 0, 0, 0, 0]),
+            stats([8902, 34, 0, 0, 0, 12, 0, 0, 0]),
+            stats([197281, 1576, 0, 0, 0, 469, 0, 0, 8]),
         ];
 
         let game = Game::new();
-        let mut levels = [Level {
-            moves: 0,
-            captures: 0,
-            checks: 0,
-        }; 4];
-        perft_ply(&mut levels, &game, 4);
-
-        assert_eq!(correct_levels, levels);
+        assert_eq!(correct_levels, perft_levels(&game));
     }
 
     #[test]
     pub fn perft_in() {
         let correct_levels = [
-            Level {
-                moves: 14,
-                captures: 1,
-                checks: 2,
-            },
-            Level {
-                moves: 191,
-                captures: 14,
-                checks: 10,
-            },
-            // Level {
-            //     moves: 2812,
-            //     captures: 209,
-            //     checks: 267,
-            // },
-            // Level {
-            //     moves: 43238,
-            //     captures: 3348,
-            //     checks: 1680,
-            // },
+            stats([14, 1, 0, 0, 0, 2, 0, 0, 0]),
+            stats([191, 14, 0, 0, 0, 10, 0, 0, 0]),
+            stats([2812, 209, 2, 0, 0, 267, 3, 0, 0]),
+            stats([43238, 3348, 123, 0, 0, 1680, 106, 0, 17]),
         ];
 
-        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -".to_string());
-        let mut levels = [Level {
-            moves: 0,
-            captures: 0,
-            checks: 0,
-        }; 2];
-        perft_ply(&mut levels, &game, 2);
+        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -".to_string()).unwrap();
+        assert_eq!(correct_levels, perft_levels(&game));
+    }
 
-        assert_eq!(correct_levels, levels);
+    #[test]
+    pub fn piece_list_add_and_remove() {
+        let mut pieces = PieceList::new();
+        pieces.add(Piece::Rook, 0x00);
+        pieces.add(Piece::Rook, 0x07);
+        pieces.add(Piece::Rook, 0x43);
+
+        pieces.remove(Piece::Rook, 0x00);
+        pieces.relocate(Piece::Rook, 0x43, 0x44);
+
+        assert_eq!(2, pieces.len());
+        assert!(pieces.contains(Piece::Rook, 0x07));
+        assert!(pieces.contains(Piece::Rook, 0x44));
+        assert!(!pieces.contains(Piece::Rook, 0x00));
+        assert!(!pieces.contains(Piece::Rook, 0x43));
     }
 
     #[test]
-    pub fn parse_fen_matches_default() {
-        let game = Game::new();
-        let game_fen =
-            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0".to_string());
+    // See https://www.chessprogramming.org/Perft_Results for these positions
+    pub fn perft_kiwipete() {
+        let correct_levels = [
+            stats([48, 8, 0, 2, 0, 0, 0, 0, 0]),
+            stats([2039, 351, 1, 91, 0, 3, 0, 0, 0]),
+            stats([97862, 17102, 45, 3162, 0, 993, 0, 0, 1]),
+        ];
 
-        assert_eq!(game.board, game_fen.board);
+        let game = Game::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_string(),
+        )
+        .unwrap();
+        assert_eq!(correct_levels, perft_levels(&game));
     }
 
     #[test]
-    pub fn parse_fen_with_inspection() {
-        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -".to_string());
+    pub fn perft_black_to_move() {
+        let correct_levels = [
+            stats([6, 0, 0, 0, 0, 0, 0, 0, 0]),
+            stats([264, 87, 0, 6, 48, 10, 0, 0, 0]),
+            stats([9467, 1021, 4, 0, 120, 38, 2, 0, 22]),
+        ];
 
-        assert_eq!(game.black.pieces[&Piece::Pawn(0)], 0x62);
-        assert_eq!(game.black.pieces[&Piece::Pawn(1)], 0x53);
-        assert_eq!(game.white.pieces[&Piece::King], 0x40);
-        assert_eq!(game.white.pieces[&Piece::Pawn(0)], 0x41);
-        assert_eq!(game.black.pieces[&Piece::Rook(false)], 0x47);
-        assert_eq!(game.white.pieces[&Piece::Rook(false)], 0x31);
-        assert_eq!(game.black.pieces[&Piece::Pawn(2)], 0x35);
-        assert_eq!(game.black.pieces[&Piece::King], 0x37);
-        assert_eq!(game.white.pieces[&Piece::Pawn(1)], 0x14);
-        assert_eq!(game.white.pieces[&Piece::Pawn(2)], 0x16);
+        let game = Game::from_fen(
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1".to_string(),
+        )
+        .unwrap();
+        assert_eq!(correct_levels, perft_levels(&game));
+    }
 
-        assert_eq!(5, game.white.pieces.len());
-        assert_eq!(5, game.black.pieces.len());
+    #[test]
+    pub fn stages_make_up_all_moves() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -",
+        ] {
+            let game = Game::from_fen(fen.to_string()).unwrap();
+            let all: HashSet<Move> = game.generate_moves().into_iter().collect();
+            let captures = game.generate_stage(Stage::Captures);
+            let quiets = game.generate_stage(Stage::Quiets);
+            let checks = game.generate_stage(Stage::QuietChecks);
+
+            assert_eq!(all.len(), captures.len() + quiets.len());
+            assert_eq!(all, captures.iter().chain(quiets.iter()).copied().collect());
+            assert!(captures
+                .iter()
+                .all(|m| m.is_capture() || m.promotion().is_some()));
+
+            // Quiet checks are exactly the quiet moves that give check
+            let expected: Vec<Move> = quiets
+                .into_iter()
+                .filter(|m| game.make_move(*m).unwrap().get_player().check)
+                .collect();
+            assert_eq!(expected, checks);
+        }
     }
 
     #[test]
-    pub fn parse_fen_with_check() {
-        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/r7 w - -".to_string());
+    pub fn stages_from_kiwipete() {
+        let game = Game::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -".to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(8, game.generate_stage(Stage::Captures).len());
+        assert_eq!(40, game.generate_stage(Stage::Quiets).len());
+        assert!(game.generate_stage(Stage::QuietChecks).is_empty());
+        assert!(Game::new().generate_stage(Stage::Captures).is_empty());
+    }
 
-        assert!(game.white.check);
-        assert!(!game.black.check);
+    #[test]
+    pub fn perft_promotions() {
+        let game =
+            Game::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8".to_string())
+                .unwrap();
+        assert_eq!(
+            [44, 1486, 62379],
+            perft_levels::<3>(&game).map(|level| level.nodes)
+        );
+    }
+
+    #[test]
+    pub fn perft_chess960() {
+        let game = Game::from_fen(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            [21, 528, 12189],
+            perft_levels::<3>(&game).map(|level| level.nodes)
+        );
     }
 
     #[test]
-    pub fn parse_fen_with_black_check() {
-        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/7R w - -".to_string());
+    pub fn chess960_start_positions() {
+        let back_rank = |index| {
+            Game::from_chess960(index)
+                .unwrap()
+                .to_fen()
+                .split('/')
+                .next()
+                .unwrap()
+                .to_string()
+        };
 
-        assert!(!game.white.check);
-        assert!(game.black.check);
+        assert_eq!(Game::new(), Game::from_chess960(518).unwrap());
+        assert_eq!("bbqnnrkr", back_rank(0));
+        assert_eq!("rkrnnqbb", back_rank(959));
+        assert_eq!(None, Game::from_chess960(960));
+        assert_eq!(
+            960,
+            (0..960).map(back_rank).collect::<HashSet<String>>().len()
+        );
+    }
+
+    #[test]
+    pub fn chess960_castling() {
+        let castle = |fen: &str| {
+            let game = Game::from_fen(fen.to_string()).unwrap();
+            let m = game.generate_moves().into_iter().find(|m| m.is_castle());
+            m.map(|m| {
+                let mut castled = game.clone();
+                let undo = castled.do_move(m);
+                let fen = castled.to_fen();
+                castled.undo_move(m, undo);
+                assert_eq!(game, castled);
+                fen
+            })
+        };
+
+        // The king lands on the rook's space, swaps with it, or stays put while the rook moves
+        assert_eq!(
+            Some("4k3/8/8/8/8/8/8/5RK1 b - - 1 1".to_string()),
+            castle("4k3/8/8/8/8/8/8/4K1R1 w K - 0 1")
+        );
+        assert_eq!(
+            Some("4k3/8/8/8/8/8/8/5RK1 b - - 1 1".to_string()),
+            castle("4k3/8/8/8/8/8/8/5KR1 w K - 0 1")
+        );
+        assert_eq!(
+            Some("4k3/8/8/8/8/8/8/5RK1 b - - 1 1".to_string()),
+            castle("4k3/8/8/8/8/8/8/6KR w K - 0 1")
+        );
+        assert_eq!(
+            Some("2kr4/8/8/8/8/8/8/4K3 w - - 1 2".to_string()),
+            castle("1rk5/8/8/8/8/8/8/4K3 b q - 0 1")
+        );
+
+        // Pieces in the way of the rook, and a queen the rook was shielding the king from
+        assert_eq!(None, castle("4k3/8/8/8/8/8/8/1R1NK3 w Q - 0 1"));
+        assert_eq!(None, castle("4k3/8/8/8/8/8/8/qR2K3 w Q - 0 1"));
+    }
+
+    // The destinations of the moves from a space, sorted so they are easy to compare
+    fn destinations(moves: &[Move], src: usize) -> Vec<usize> {
+        let mut destinations: Vec<usize> = moves
+            .iter()
+            .filter(|m| m.from() == src)
+            .map(|m| m.to())
+            .collect();
+        destinations.sort();
+        destinations
+    }
+
+    #[test]
+    pub fn pinned_pieces_stay_on_the_pin() {
+        let game = Game::from_fen("4r2k/8/8/8/8/8/4B3/4K3 w - -".to_string()).unwrap();
+        assert!(destinations(&game.generate_moves(), 0x14).is_empty());
+
+        let game = Game::from_fen("4r2k/8/8/8/8/8/4R3/4K3 w - -".to_string()).unwrap();
+        assert_eq!(
+            vec![0x24, 0x34, 0x44, 0x54, 0x64, 0x74],
+            destinations(&game.generate_moves(), 0x14)
+        );
+    }
+
+    #[test]
+    pub fn check_is_blocked_or_captured() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/1B6/r3K3 w - -".to_string()).unwrap();
+        let moves = game.generate_moves();
+
+        assert_eq!(vec![0x00, 0x02], destinations(&moves, 0x11));
+        // The king can't step back along the rook's line
+        assert_eq!(vec![0x13, 0x14, 0x15], destinations(&moves, 0x04));
+    }
+
+    #[test]
+    pub fn double_check_only_moves_the_king() {
+        let game = Game::from_fen("4k3/8/8/8/1b6/8/2N5/r3K3 w - -".to_string()).unwrap();
+        let moves = game.generate_moves();
+
+        assert_eq!(2, moves.len());
+        assert_eq!(vec![0x14, 0x15], destinations(&moves, 0x04));
+    }
+
+    #[test]
+    pub fn make_move_updates_clocks() {
+        // Move white knight to C3, black pawn to E5 & white knight to B5
+        let game = Game::new().force_move(Move::new(0x01, 0x22)).unwrap();
+        assert_eq!(1, game.halfmove_clock);
+        assert_eq!(1, game.fullmove_number);
+
+        let game = game.force_move(Move::new(0x64, 0x44)).unwrap();
+        assert_eq!(0, game.halfmove_clock);
+        assert_eq!(2, game.fullmove_number);
+
+        let game = game.force_move(Move::new(0x22, 0x41)).unwrap();
+        assert_eq!(1, game.halfmove_clock);
+        assert_eq!(2, game.fullmove_number);
     }
 
     #[test]
@@ -981,9 +1830,9 @@ mod tests {
         let queen = game.board[0x73];
 
         // Move black queen to G6
-        let game = game.make_move(0x73, 0x55).unwrap();
+        let game = game.force_move(Move::new(0x73, 0x55)).unwrap();
 
-        assert_eq!(0x55, game.black.pieces[&Piece::Queen]);
+        assert!(game.black.pieces.contains(Piece::Queen, 0x55));
         assert_eq!(None, game.board[0x73]);
         assert_eq!(queen, game.board[0x55]);
         assert!(!game.white.check);
@@ -996,9 +1845,9 @@ mod tests {
         let queen = game.board[0x03];
 
         // Move white queen to G6
-        let game = game.make_move(0x03, 0x55).unwrap();
+        let game = game.force_move(Move::new(0x03, 0x55)).unwrap();
 
-        assert_eq!(0x55, game.white.pieces[&Piece::Queen]);
+        assert!(game.white.pieces.contains(Piece::Queen, 0x55));
         assert_eq!(None, game.board[0x03]);
         assert_eq!(queen, game.board[0x55]);
         assert!(!game.white.check);
@@ -1012,29 +1861,114 @@ mod tests {
         let queen = game.board[0x73];
 
         // Move black queen to D2
-        let game = game.make_move(0x73, 0x13).unwrap();
+        let game = game.force_move(Move::new(0x73, 0x13)).unwrap();
 
-        assert_eq!(0x13, game.black.pieces[&Piece::Queen]);
+        assert!(game.black.pieces.contains(Piece::Queen, 0x13));
         assert_eq!(None, game.board[0x73]);
         assert_eq!(queen, game.board[0x13]);
-        assert!(!game.white.pieces.contains_key(&Piece::Pawn(3)));
+        assert!(!game.white.pieces.contains(Piece::Pawn, 0x13));
         assert!(game.white.check);
         assert!(!game.black.check);
     }
 
+    #[test]
+    pub fn generate_moves_from_start() {
+        let moves = Game::new().generate_moves();
+
+        assert_eq!(20, moves.len());
+        assert_eq!(8, moves.iter().filter(|m| m.is_double_push()).count());
+        assert!(moves
+            .iter()
+            .all(|m| !m.is_capture() && m.promotion().is_none()));
+    }
+
+    #[test]
+    pub fn make_move_into_check_is_rejected() {
+        let game = Game::from_fen("k3r3/8/8/8/8/8/4R3/4K3 w - -".to_string()).unwrap();
+
+        // The white rook is pinned on the E file
+        assert_eq!(None, game.make_move(Move::new(0x14, 0x13)));
+        assert!(game.make_move(Move::new(0x14, 0x64)).is_some());
+        assert!(game
+            .generate_moves()
+            .iter()
+            .all(|m| m.from() != 0x14 || m.to() & 0x07 == 0x04));
+    }
+
+    #[test]
+    pub fn make_move_only_plays_legal_moves() {
+        // Built from its spaces, castling is still played as castling
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1".to_string()).unwrap();
+        assert_eq!(
+            "4k3/8/8/8/8/8/8/5RK1 b - - 1 1",
+            game.make_move(Move::new(0x04, 0x06)).unwrap().to_fen()
+        );
+        assert_eq!(None, game.make_move(Move::new(0x04, 0x24)));
+        assert_eq!(None, game.make_move(Move::new(0x33, 0x34)));
+        assert_eq!(
+            None,
+            BitboardGame::from(&game).make_move(Move::new(0x33, 0x34))
+        );
+
+        // And a double push can be taken en passant
+        let game = Game::new().make_move(Move::new(0x14, 0x34)).unwrap();
+        assert!(game.to_fen().contains(" e3 "));
+    }
+
+    #[test]
+    pub fn do_move_matches_make_move() {
+        let mut game = Game::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -".to_string(),
+        )
+        .unwrap();
+
+        for m in game.clone().generate_moves() {
+            let expected = game.make_move(m).unwrap();
+            let undo = game.do_move(m);
+            assert_eq!(expected, game);
+            game.undo_move(m, undo);
+        }
+    }
+
+    #[test]
+    pub fn undo_move_restores_game() {
+        // Castling, en passant, promotion with and without capture, and captures of castling rooks
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r3k2r/8/8/8/4Pp2/8/8/R3K2R b KQkq e3 3 42",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let mut game = Game::from_fen(fen.to_string()).unwrap();
+            let original = game.clone();
+
+            for m in original.generate_moves() {
+                let undo = game.do_move(m);
+                for reply in game.generate_moves() {
+                    let reply_undo = game.do_move(reply);
+                    // The occupied squares are kept as moves are made, so they must still match the board
+                    assert_eq!(Game::occupancy(&game.board), game.occupied, "{}", fen);
+                    game.undo_move(reply, reply_undo);
+                }
+                game.undo_move(m, undo);
+                assert_eq!(original, game);
+            }
+        }
+    }
+
     #[test]
     pub fn make_move_puts_king_in_check() {
         let game = Game::new();
 
         // Move white king to D4 & black queen to F6
         let game = game
-            .make_move(4, 0x33)
+            .force_move(Move::new(4, 0x33))
             .unwrap()
-            .make_move(0x73, 0x55)
+            .force_move(Move::new(0x73, 0x55))
             .unwrap();
 
-        assert_eq!(0x33, game.white.pieces[&Piece::King]);
-        assert_eq!(0x55, game.black.pieces[&Piece::Queen]);
+        assert_eq!(0x33, game.white.pieces.king());
+        assert!(game.black.pieces.contains(Piece::Queen, 0x55));
         assert!(game.white.check);
         assert!(!game.black.check);
     }
@@ -1045,12 +1979,12 @@ mod tests {
         let mut moves = vec![];
 
         // E1
-        game.generate_king_moves(&mut moves, game.white.pieces[&Piece::King]);
+        game.generate_king_moves(&mut moves, game.white.pieces.king(), &game.restrictions());
         assert_eq!(0, moves.len());
 
         game.current_player = Side::Black;
         // E8
-        game.generate_king_moves(&mut moves, game.black.pieces[&Piece::King]);
+        game.generate_king_moves(&mut moves, game.black.pieces.king(), &game.restrictions());
         assert_eq!(0, moves.len());
     }
 
@@ -1060,12 +1994,12 @@ mod tests {
 
         // Move white king to E4 & black pawn to E5
         let game = Game::new()
-            .make_move(0x04, 0x34)
+            .force_move(Move::new(0x04, 0x34))
             .unwrap()
-            .make_move(0x64, 0x44)
+            .force_move(Move::new(0x64, 0x44))
             .unwrap();
 
-        game.generate_king_moves(&mut moves, game.white.pieces[&Piece::King]);
+        game.generate_king_moves(&mut moves, game.white.pieces.king(), &game.restrictions());
         assert_eq!(6, moves.len());
     }
 
@@ -1075,14 +2009,14 @@ mod tests {
 
         // Move white king to D5, black pawn to C5 & black bishop to C7
         let mut game = Game::new()
-            .make_move(0x04, 0x43)
+            .force_move(Move::new(0x04, 0x43))
             .unwrap()
-            .make_move(0x62, 0x42)
+            .force_move(Move::new(0x62, 0x42))
             .unwrap();
         game.current_player = Side::Black;
-        game = game.make_move(0x75, 0x62).unwrap();
+        game = game.force_move(Move::new(0x75, 0x62)).unwrap();
 
-        game.generate_king_moves(&mut moves, game.white.pieces[&Piece::King]);
+        game.generate_king_moves(&mut moves, game.white.pieces.king(), &game.restrictions());
         assert_eq!(3, moves.len());
     }
 
@@ -1091,32 +2025,157 @@ mod tests {
         let mut moves = vec![];
 
         // Move white king to D4, white rook to B4, black rook to D6
-        let mut game = Game::new().make_move(0x04, 0x33).unwrap();
+        let mut game = Game::new().force_move(Move::new(0x04, 0x33)).unwrap();
         game.current_player = Side::White;
         game = game
-            .make_move(0x00, 0x31)
+            .force_move(Move::new(0x00, 0x31))
             .unwrap()
-            .make_move(0x70, 0x53)
+            .force_move(Move::new(0x70, 0x53))
             .unwrap();
         game.current_player = Side::White;
 
-        game.generate_king_moves(&mut moves, game.white.pieces[&Piece::King]);
+        game.generate_king_moves(&mut moves, game.white.pieces.king(), &game.restrictions());
 
         assert_eq!(6, moves.len());
     }
 
+    #[test]
+    pub fn king_moves_castle_kingside() {
+        let mut moves = vec![];
+
+        // Move white bishop to F3 & white knight to H3
+        let mut game = Game::new().force_move(Move::new(0x05, 0x25)).unwrap();
+        game.current_player = Side::White;
+        game = game.force_move(Move::new(0x06, 0x27)).unwrap();
+        game.current_player = Side::White;
+
+        game.generate_king_moves(&mut moves, game.white.pieces.king(), &game.restrictions());
+        assert_eq!(2, moves.len());
+        assert!(moves.iter().any(|m| m.is_castle() && m.to() == 0x06));
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+
+        let castled = games
+            .iter()
+            .find(|m| m.white.pieces.king() == 0x06)
+            .unwrap();
+        assert!(castled.white.pieces.contains(Piece::Rook, 0x05));
+        assert_eq!(castled.board[0x05], game.board[0x07]);
+        assert_eq!(None, castled.board[0x07]);
+        assert!(!castled.white.castle_kingside);
+        assert!(!castled.white.castle_queenside);
+    }
+
+    #[test]
+    pub fn king_moves_castle_queenside() {
+        let mut moves = vec![];
+
+        // Move black knight to A6, black bishop to E6 & black queen to D6
+        let mut game = Game::new();
+        game.current_player = Side::Black;
+        game = game.force_move(Move::new(0x71, 0x50)).unwrap();
+        game.current_player = Side::Black;
+        game = game.force_move(Move::new(0x72, 0x54)).unwrap();
+        game.current_player = Side::Black;
+        game = game.force_move(Move::new(0x73, 0x53)).unwrap();
+        game.current_player = Side::Black;
+
+        game.generate_king_moves(&mut moves, game.black.pieces.king(), &game.restrictions());
+        assert_eq!(2, moves.len());
+        assert!(moves.iter().any(|m| m.is_castle() && m.to() == 0x72));
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+
+        let castled = games
+            .iter()
+            .find(|m| m.black.pieces.king() == 0x72)
+            .unwrap();
+        assert!(castled.black.pieces.contains(Piece::Rook, 0x73));
+        assert_eq!(None, castled.board[0x70]);
+        assert!(!castled.black.castle_kingside);
+        assert!(!castled.black.castle_queenside);
+    }
+
+    #[test]
+    pub fn king_moves_castle_through_check() {
+        let mut moves = vec![];
+
+        // Move white bishop to C4, white knight to H3, white pawn to G4 & black rook to F6
+        let mut game = Game::new().force_move(Move::new(0x05, 0x32)).unwrap();
+        game.current_player = Side::White;
+        game = game.force_move(Move::new(0x06, 0x27)).unwrap();
+        game.current_player = Side::White;
+        game = game
+            .force_move(Move::new(0x15, 0x36))
+            .unwrap()
+            .force_move(Move::new(0x77, 0x55))
+            .unwrap();
+
+        game.generate_king_moves(&mut moves, game.white.pieces.king(), &game.restrictions());
+        assert_eq!(0, moves.len());
+    }
+
+    #[test]
+    pub fn king_moves_castle_out_of_check() {
+        let mut moves = vec![];
+
+        // Move white bishop to C4, white knight to H3, white pawn to E4 & black queen to E5
+        let mut game = Game::new().force_move(Move::new(0x05, 0x32)).unwrap();
+        game.current_player = Side::White;
+        game = game.force_move(Move::new(0x06, 0x27)).unwrap();
+        game.current_player = Side::White;
+        game = game
+            .force_move(Move::new(0x14, 0x34))
+            .unwrap()
+            .force_move(Move::new(0x73, 0x64))
+            .unwrap();
+        game.current_player = Side::Black;
+        game = game.force_move(Move::new(0x64, 0x24)).unwrap();
+
+        assert!(game.white.check);
+        game.generate_king_moves(&mut moves, game.white.pieces.king(), &game.restrictions());
+        assert!(moves.iter().all(|m| !m.is_castle()));
+    }
+
+    #[test]
+    pub fn castling_rights_lost_when_pieces_move() {
+        // Move white king to E2 & black king rook to H6
+        let mut game = Game::new().force_move(Move::new(0x14, 0x34)).unwrap();
+        game.current_player = Side::White;
+        game = game
+            .force_move(Move::new(0x04, 0x14))
+            .unwrap()
+            .force_move(Move::new(0x77, 0x57))
+            .unwrap();
+
+        assert!(!game.white.castle_kingside);
+        assert!(!game.white.castle_queenside);
+        assert!(!game.black.castle_kingside);
+        assert!(game.black.castle_queenside);
+    }
+
+    #[test]
+    pub fn castling_rights_lost_when_rook_captured() {
+        let mut game = Game::new();
+        game.current_player = Side::Black;
+
+        // Black queen takes the white queen's rook on A1
+        game = game.force_move(Move::new(0x73, 0x00)).unwrap();
+
+        assert!(game.white.castle_kingside);
+        assert!(!game.white.castle_queenside);
+    }
+
     #[test]
     pub fn queen_moves_from_start() {
         let mut game = Game::new();
         let mut moves = vec![];
 
         // D1
-        game.generate_queen_moves(&mut moves, game.white.pieces[&Piece::Queen]);
+        game.generate_queen_moves(&mut moves, 0x03, &game.restrictions());
         assert_eq!(0, moves.len());
 
         game.current_player = Side::Black;
         // D8
-        game.generate_queen_moves(&mut moves, game.black.pieces[&Piece::Queen]);
+        game.generate_queen_moves(&mut moves, 0x73, &game.restrictions());
         assert_eq!(0, moves.len());
     }
 
@@ -1125,9 +2184,9 @@ mod tests {
         let mut moves = vec![];
 
         // Move white queen to D5
-        let game = Game::new().make_move(0x03, 0x43).unwrap();
+        let game = Game::new().force_move(Move::new(0x03, 0x43)).unwrap();
 
-        game.generate_queen_moves(&mut moves, game.white.pieces[&Piece::Queen]);
+        game.generate_queen_moves(&mut moves, 0x43, &game.restrictions());
         assert_eq!(19, moves.len());
     }
 
@@ -1137,18 +2196,18 @@ mod tests {
         let mut moves = vec![];
 
         // C1
-        game.generate_bishop_moves(&mut moves, game.white.pieces[&Piece::Bishop(false)]);
+        game.generate_bishop_moves(&mut moves, 0x02, &game.restrictions());
         assert_eq!(0, moves.len());
         // F1
-        game.generate_bishop_moves(&mut moves, game.white.pieces[&Piece::Bishop(true)]);
+        game.generate_bishop_moves(&mut moves, 0x05, &game.restrictions());
         assert_eq!(0, moves.len());
 
         game.current_player = Side::Black;
         // C8
-        game.generate_bishop_moves(&mut moves, game.black.pieces[&Piece::Bishop(false)]);
+        game.generate_bishop_moves(&mut moves, 0x72, &game.restrictions());
         assert_eq!(0, moves.len());
         // F8
-        game.generate_bishop_moves(&mut moves, game.black.pieces[&Piece::Bishop(true)]);
+        game.generate_bishop_moves(&mut moves, 0x75, &game.restrictions());
         assert_eq!(0, moves.len());
     }
 
@@ -1157,9 +2216,9 @@ mod tests {
         let mut moves = vec![];
 
         // Move white bishop to D5
-        let game = Game::new().make_move(0x05, 0x43).unwrap();
+        let game = Game::new().force_move(Move::new(0x05, 0x43)).unwrap();
 
-        game.generate_bishop_moves(&mut moves, game.white.pieces[&Piece::Bishop(true)]);
+        game.generate_bishop_moves(&mut moves, 0x43, &game.restrictions());
         assert_eq!(8, moves.len());
     }
 
@@ -1168,9 +2227,9 @@ mod tests {
         let mut moves = vec![];
 
         // Move white bishop to B5
-        let game = Game::new().make_move(0x05, 0x41).unwrap();
+        let game = Game::new().force_move(Move::new(0x05, 0x41)).unwrap();
 
-        game.generate_bishop_moves(&mut moves, game.white.pieces[&Piece::Bishop(true)]);
+        game.generate_bishop_moves(&mut moves, 0x41, &game.restrictions());
         assert_eq!(6, moves.len());
     }
 
@@ -1180,18 +2239,18 @@ mod tests {
         let mut moves = vec![];
 
         // A1
-        game.generate_rook_moves(&mut moves, game.white.pieces[&Piece::Rook(false)]);
+        game.generate_rook_moves(&mut moves, 0x00, &game.restrictions());
         assert_eq!(0, moves.len());
         // H1
-        game.generate_rook_moves(&mut moves, game.white.pieces[&Piece::Rook(true)]);
+        game.generate_rook_moves(&mut moves, 0x07, &game.restrictions());
         assert_eq!(0, moves.len());
 
         game.current_player = Side::Black;
         // A8
-        game.generate_rook_moves(&mut moves, game.black.pieces[&Piece::Rook(false)]);
+        game.generate_rook_moves(&mut moves, 0x70, &game.restrictions());
         assert_eq!(0, moves.len());
         // H8
-        game.generate_rook_moves(&mut moves, game.black.pieces[&Piece::Rook(true)]);
+        game.generate_rook_moves(&mut moves, 0x77, &game.restrictions());
         assert_eq!(0, moves.len());
     }
 
@@ -1200,9 +2259,9 @@ mod tests {
         let mut moves = vec![];
 
         // Move white rook to D5
-        let game = Game::new().make_move(0x00, 0x43).unwrap();
+        let game = Game::new().force_move(Move::new(0x00, 0x43)).unwrap();
 
-        game.generate_rook_moves(&mut moves, game.white.pieces[&Piece::Rook(false)]);
+        game.generate_rook_moves(&mut moves, 0x43, &game.restrictions());
         assert_eq!(11, moves.len());
     }
 
@@ -1212,18 +2271,18 @@ mod tests {
         let mut moves = vec![];
 
         // B1
-        game.generate_knight_moves(&mut moves, game.white.pieces[&Piece::Knight(false)]);
+        game.generate_knight_moves(&mut moves, 0x01, &game.restrictions());
         assert_eq!(2, moves.len());
         // G1
-        game.generate_knight_moves(&mut moves, game.white.pieces[&Piece::Knight(true)]);
+        game.generate_knight_moves(&mut moves, 0x06, &game.restrictions());
         assert_eq!(4, moves.len());
 
         game.current_player = Side::Black;
         // B8
-        game.generate_knight_moves(&mut moves, game.black.pieces[&Piece::Knight(false)]);
+        game.generate_knight_moves(&mut moves, 0x71, &game.restrictions());
         assert_eq!(6, moves.len());
         // G8
-        game.generate_knight_moves(&mut moves, game.black.pieces[&Piece::Knight(true)]);
+        game.generate_knight_moves(&mut moves, 0x76, &game.restrictions());
         assert_eq!(8, moves.len());
     }
 
@@ -1232,10 +2291,10 @@ mod tests {
         let mut moves = vec![];
 
         // Move white knight to D5
-        let mut game = Game::new().make_move(0x01, 0x43).unwrap();
+        let mut game = Game::new().force_move(Move::new(0x01, 0x43)).unwrap();
         game.current_player = Side::White;
 
-        game.generate_knight_moves(&mut moves, game.white.pieces[&Piece::Knight(false)]);
+        game.generate_knight_moves(&mut moves, 0x43, &game.restrictions());
         assert_eq!(8, moves.len());
     }
 
@@ -1244,10 +2303,10 @@ mod tests {
         let mut moves = vec![];
 
         // Move white knight to A5
-        let mut game = Game::new().make_move(0x01, 0x40).unwrap();
+        let mut game = Game::new().force_move(Move::new(0x01, 0x40)).unwrap();
         game.current_player = Side::White;
 
-        game.generate_knight_moves(&mut moves, game.white.pieces[&Piece::Knight(false)]);
+        game.generate_knight_moves(&mut moves, 0x40, &game.restrictions());
         assert_eq!(4, moves.len());
     }
 
@@ -1257,18 +2316,18 @@ mod tests {
         let mut moves = vec![];
 
         // A2
-        game.generate_pawn_moves(&mut moves, game.white.pieces[&Piece::Pawn(0)]);
+        game.generate_pawn_moves(&mut moves, 0x10, &game.restrictions());
         assert_eq!(2, moves.len());
         // E2
-        game.generate_pawn_moves(&mut moves, game.white.pieces[&Piece::Pawn(4)]);
+        game.generate_pawn_moves(&mut moves, 0x14, &game.restrictions());
         assert_eq!(4, moves.len());
 
         game.current_player = Side::Black;
         // B7
-        game.generate_pawn_moves(&mut moves, game.black.pieces[&Piece::Pawn(1)]);
+        game.generate_pawn_moves(&mut moves, 0x61, &game.restrictions());
         assert_eq!(6, moves.len());
         // G7
-        game.generate_pawn_moves(&mut moves, game.black.pieces[&Piece::Pawn(6)]);
+        game.generate_pawn_moves(&mut moves, 0x66, &game.restrictions());
         assert_eq!(8, moves.len());
     }
 
@@ -1278,17 +2337,17 @@ mod tests {
 
         // Move white pawn to D4 & black pawn to C5
         let mut game = Game::new()
-            .make_move(0x13, 0x33)
+            .force_move(Move::new(0x13, 0x33))
             .unwrap()
-            .make_move(0x62, 0x42)
+            .force_move(Move::new(0x62, 0x42))
             .unwrap();
         game.current_player = Side::White;
 
-        game.generate_pawn_moves(&mut moves, game.white.pieces[&Piece::Pawn(3)]);
+        game.generate_pawn_moves(&mut moves, 0x33, &game.restrictions());
         assert_eq!(2, moves.len());
 
         game.current_player = Side::Black;
-        game.generate_pawn_moves(&mut moves, game.black.pieces[&Piece::Pawn(2)]);
+        game.generate_pawn_moves(&mut moves, 0x42, &game.restrictions());
         assert_eq!(4, moves.len());
     }
 
@@ -1298,12 +2357,12 @@ mod tests {
 
         // Move white pawn to D5 & white pawn to D6
         let game = Game::new()
-            .make_move(0x13, 0x43)
+            .force_move(Move::new(0x13, 0x43))
             .unwrap()
-            .make_move(0x14, 0x53)
+            .force_move(Move::new(0x14, 0x53))
             .unwrap();
 
-        game.generate_pawn_moves(&mut moves, game.white.pieces[&Piece::Pawn(3)]);
+        game.generate_pawn_moves(&mut moves, 0x43, &game.restrictions());
         assert_eq!(0, moves.len());
     }
 
@@ -1313,12 +2372,12 @@ mod tests {
 
         // Move white pawn to D5 & black pawn to D6
         let game = Game::new()
-            .make_move(0x13, 0x43)
+            .force_move(Move::new(0x13, 0x43))
             .unwrap()
-            .make_move(0x63, 0x53)
+            .force_move(Move::new(0x63, 0x53))
             .unwrap();
 
-        game.generate_pawn_moves(&mut moves, game.white.pieces[&Piece::Pawn(3)]);
+        game.generate_pawn_moves(&mut moves, 0x43, &game.restrictions());
         assert_eq!(0, moves.len());
     }
 
@@ -1327,11 +2386,198 @@ mod tests {
         let mut moves = vec![];
 
         // Move white pawn to D6
-        let mut game = Game::new().make_move(0x13, 0x53).unwrap();
+        let mut game = Game::new().force_move(Move::new(0x13, 0x53)).unwrap();
         game.current_player = Side::White;
 
-        game.generate_pawn_moves(&mut moves, game.white.pieces[&Piece::Pawn(3)]);
+        game.generate_pawn_moves(&mut moves, 0x53, &game.restrictions());
+        assert_eq!(2, moves.len());
+    }
+
+    #[test]
+    pub fn pawn_moves_en_passant() {
+        let mut moves = vec![];
+
+        // Move white pawn to E5 & black pawn to D5
+        let game = Game::new()
+            .force_move(Move::new(0x14, 0x44))
+            .unwrap()
+            .force_move(Move::new(0x63, 0x43))
+            .unwrap();
+        assert_eq!(Some(0x53), game.en_passant);
+
+        game.generate_pawn_moves(&mut moves, 0x44, &game.restrictions());
+        assert_eq!(2, moves.len());
+        assert!(moves
+            .iter()
+            .any(|m| m.is_en_passant() && m.is_capture() && m.to() == 0x53));
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+
+        let captured = games
+            .iter()
+            .find(|m| m.white.pieces.contains(Piece::Pawn, 0x53))
+            .unwrap();
+        assert_eq!(None, captured.board[0x43]);
+        assert!(!captured.black.pieces.contains(Piece::Pawn, 0x43));
+        assert_eq!(None, captured.en_passant);
+    }
+
+    #[test]
+    pub fn black_pawn_moves_en_passant() {
+        let mut game = Game::new();
+        let mut moves = vec![];
+        game.current_player = Side::Black;
+
+        // Move black pawn to B4 & white pawn to A4
+        game = game
+            .force_move(Move::new(0x61, 0x31))
+            .unwrap()
+            .force_move(Move::new(0x10, 0x30))
+            .unwrap();
+        assert_eq!(Some(0x20), game.en_passant);
+
+        game.generate_pawn_moves(&mut moves, 0x31, &game.restrictions());
         assert_eq!(2, moves.len());
+        assert!(moves.iter().any(|m| m.is_en_passant() && m.to() == 0x20));
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+
+        let captured = games
+            .iter()
+            .find(|m| m.black.pieces.contains(Piece::Pawn, 0x20))
+            .unwrap();
+        assert_eq!(None, captured.board[0x30]);
+        assert!(!captured.white.pieces.contains(Piece::Pawn, 0x30));
+    }
+
+    #[test]
+    pub fn pawn_moves_en_passant_expires() {
+        let mut moves = vec![];
+
+        // Move white pawn to E5, black pawn to D5, white knight to A3 & black knight to A6
+        let game = Game::new()
+            .force_move(Move::new(0x14, 0x44))
+            .unwrap()
+            .force_move(Move::new(0x63, 0x43))
+            .unwrap()
+            .force_move(Move::new(0x01, 0x20))
+            .unwrap()
+            .force_move(Move::new(0x71, 0x50))
+            .unwrap();
+        assert_eq!(None, game.en_passant);
+
+        game.generate_pawn_moves(&mut moves, 0x44, &game.restrictions());
+        assert_eq!(1, moves.len());
+    }
+
+    #[test]
+    pub fn pawn_moves_en_passant_pinned() {
+        let mut moves = vec![];
+
+        // Taking en passant would leave both pawns off the fifth rank, exposing the king to the rook
+        let mut game = Game::from_fen("8/8/8/KPp4r/8/8/8/7k w - -".to_string()).unwrap();
+        game.en_passant = Some(0x52);
+
+        game.generate_pawn_moves(&mut moves, 0x41, &game.restrictions());
+        assert_eq!(1, moves.len());
+    }
+
+    #[test]
+    pub fn pawn_moves_promotion() {
+        let mut moves = vec![];
+
+        let game = Game::from_fen("8/P7/8/8/8/8/8/k6K w - -".to_string()).unwrap();
+
+        game.generate_pawn_moves(&mut moves, 0x60, &game.restrictions());
+        assert_eq!(4, moves.len());
+        assert!(moves.iter().all(|m| m.promotion().is_some()));
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+
+        let queen = games
+            .iter()
+            .find(|m| m.white.pieces.contains(Piece::Queen, 0x70))
+            .unwrap();
+        assert!(queen.white.pieces.contains(Piece::Queen, 0x70));
+        assert!(!queen.white.pieces.contains(Piece::Pawn, 0x60));
+        assert_eq!(
+            Some(Space {
+                piece: Piece::Queen,
+                side: Side::White
+            }),
+            queen.board[0x70]
+        );
+        assert_eq!(None, queen.board[0x60]);
+        assert!(games.iter().all(|m| m.white.pieces.len() == 2));
+    }
+
+    #[test]
+    pub fn pawn_moves_promotion_keeps_existing_pieces() {
+        let mut moves = vec![];
+
+        let game = Game::from_fen("8/P7/8/7k/8/8/8/2QR3K w - -".to_string()).unwrap();
+
+        game.generate_pawn_moves(&mut moves, 0x60, &game.restrictions());
+        assert_eq!(4, moves.len());
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+        assert!(games.iter().all(|m| m.white.pieces.len() == 4));
+
+        let queen = games
+            .iter()
+            .find(|m| m.white.pieces.contains(Piece::Queen, 0x70))
+            .unwrap();
+        assert!(queen.white.pieces.contains(Piece::Queen, 0x02));
+
+        let rook = games
+            .iter()
+            .find(|m| m.white.pieces.contains(Piece::Rook, 0x70))
+            .unwrap();
+        assert!(rook.white.pieces.contains(Piece::Rook, 0x03));
+    }
+
+    #[test]
+    pub fn promotion_fills_piece_list() {
+        // Nine rooks and a pawn, so promoting it makes as many rooks as a side can have
+        let game = test_game("7k/P6p/8/8/8/8/RRRRRRRR/RK6 w - - 0 1");
+        let m = game.parse_uci("a7a8r").unwrap();
+
+        let promoted = game.make_move(m).unwrap();
+        assert_eq!(
+            MAX_PIECES,
+            promoted.white.pieces.spaces(Piece::Rook).count()
+        );
+        assert_eq!(
+            promoted.without_history(),
+            Game::from(&BitboardGame::from(&game).make_move(m).unwrap())
+        );
+    }
+
+    #[test]
+    pub fn pawn_moves_capture_promotion() {
+        let mut moves = vec![];
+
+        let mut game = Game::from_fen("8/8/8/8/8/8/5p2/k4nNK w - -".to_string()).unwrap();
+        game.current_player = Side::Black;
+
+        game.generate_pawn_moves(&mut moves, 0x15, &game.restrictions());
+        assert_eq!(4, moves.len());
+        assert!(moves.iter().all(|m| m.is_capture()));
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+        assert!(games.iter().all(|m| m.white.pieces.len() == 1));
+        assert!(games.iter().any(|m| m.board[0x06]
+            == Some(Space {
+                piece: Piece::Knight,
+                side: Side::Black
+            })));
+    }
+
+    #[test]
+    pub fn pawn_moves_promotion_with_check() {
+        let mut moves = vec![];
+
+        let game = Game::from_fen("k7/2P5/8/8/8/8/8/7K w - -".to_string()).unwrap();
+
+        game.generate_pawn_moves(&mut moves, 0x62, &game.restrictions());
+        assert_eq!(4, moves.len());
+        let games: Vec<Game> = moves.iter().map(|m| game.make_move(*m).unwrap()).collect();
+        assert_eq!(2, games.iter().filter(|m| m.black.check).count());
     }
 
     #[test]
@@ -1339,34 +2585,34 @@ mod tests {
         let game = Game::new();
 
         // E1
-        assert!(!game.king_check(Side::White, game.white.pieces[&Piece::King]));
+        assert!(!game.king_check(Side::White, game.white.pieces.king()));
 
         // E8
-        assert!(!game.king_check(Side::Black, game.black.pieces[&Piece::King]));
+        assert!(!game.king_check(Side::Black, game.black.pieces.king()));
     }
 
     #[test]
     pub fn king_in_check_from_bishop() {
         // Move white king to D4 & black bishop to B6
         let game = Game::new()
-            .make_move(0x04, 0x33)
+            .force_move(Move::new(0x04, 0x33))
             .unwrap()
-            .make_move(0x75, 0x51)
+            .force_move(Move::new(0x75, 0x51))
             .unwrap();
 
-        assert!(game.king_check(Side::White, game.white.pieces[&Piece::King]));
+        assert!(game.king_check(Side::White, game.white.pieces.king()));
     }
 
     #[test]
     pub fn king_in_check_from_pawn() {
         // Move white king to D4 & black pawn to C5
         let game = Game::new()
-            .make_move(0x04, 0x33)
+            .force_move(Move::new(0x04, 0x33))
             .unwrap()
-            .make_move(0x67, 0x42)
+            .force_move(Move::new(0x67, 0x42))
             .unwrap();
 
-        assert!(game.king_check(Side::White, game.white.pieces[&Piece::King]));
+        assert!(game.king_check(Side::White, game.white.pieces.king()));
     }
 
     #[test]
@@ -1376,23 +2622,137 @@ mod tests {
 
         // Move black king to D4 & white pawn to E3
         game = game
-            .make_move(0x74, 0x33)
+            .force_move(Move::new(0x74, 0x33))
             .unwrap()
-            .make_move(0x16, 0x24)
+            .force_move(Move::new(0x16, 0x24))
             .unwrap();
 
-        assert!(game.king_check(Side::Black, game.black.pieces[&Piece::King]));
+        assert!(game.king_check(Side::Black, game.black.pieces.king()));
     }
 
     #[test]
     pub fn king_in_check_from_rook() {
         // Move white king to D4 & black rook to H4
         let game = Game::new()
-            .make_move(0x04, 0x33)
+            .force_move(Move::new(0x04, 0x33))
             .unwrap()
-            .make_move(0x70, 0x37)
+            .force_move(Move::new(0x70, 0x37))
             .unwrap();
 
-        assert!(game.king_check(Side::White, game.white.pieces[&Piece::King]));
+        assert!(game.king_check(Side::White, game.white.pieces.king()));
+    }
+
+    #[test]
+    pub fn king_in_check_from_king() {
+        // The kings can't stand next to each other, which a king on the eighth rank has to respect too
+        let game = Game::from_fen("4K3/8/4k3/8/8/8/8/8 w - -".to_string()).unwrap();
+        assert!(!game.king_check(Side::White, game.white.pieces.king()));
+
+        let moves = game.generate_moves();
+        assert_eq!(2, moves.len());
+        assert!(moves.iter().all(|m| m.to() == 0x73 || m.to() == 0x75));
+
+        let game = game.force_move(Move::new(0x74, 0x73)).unwrap();
+        assert!(game.is_attacked(0x63, Side::White));
+        assert!(game.is_attacked(0x63, Side::Black));
+    }
+
+    #[test]
+    pub fn attackers_of_space() {
+        let game = Game::from_fen("4k3/6b1/2n5/4p3/8/4K3/1B1RN3/3Q4 w - -".to_string()).unwrap();
+        let d4 = parse_space("d4").unwrap();
+
+        let mut white = game.attackers(d4, Side::White);
+        white.sort();
+        assert_eq!(vec![0x11, 0x13, 0x14, 0x24], white);
+        let mut black = game.attackers(d4, Side::Black);
+        black.sort();
+        assert_eq!(vec![0x44, 0x52], black);
+
+        // The queen is behind the rook, so doesn't attack along the file
+        let mut white = game.attackers(parse_space("d3").unwrap(), Side::White);
+        white.sort();
+        assert_eq!(vec![0x13, 0x24], white);
+
+        // The pawn blocks the bishop's diagonal
+        assert!(!game.is_attacked(parse_space("h8").unwrap(), Side::White));
+        assert!(game.is_attacked(parse_space("h8").unwrap(), Side::Black));
+    }
+
+    #[test]
+    pub fn status_checkmate_and_stalemate() {
+        assert_eq!(Status::Ongoing, Game::new().status());
+
+        // Fool's mate
+        let game = Game::from_fen(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3".to_string(),
+        )
+        .unwrap();
+        assert_eq!(Status::Checkmate(Side::Black), game.status());
+
+        let game = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - -".to_string()).unwrap();
+        assert_eq!(Status::Stalemate, game.status());
+    }
+
+    #[test]
+    pub fn status_insufficient_material() {
+        for (fen, status) in [
+            ("8/8/8/4k3/8/8/8/4K3 w - -", Status::InsufficientMaterial),
+            ("8/8/8/4k3/8/8/2N5/4K3 w - -", Status::InsufficientMaterial),
+            (
+                "8/8/8/2b1k3/8/8/3B4/4K3 w - -",
+                Status::InsufficientMaterial,
+            ),
+            ("8/8/8/2b1k3/8/8/2B5/4K3 w - -", Status::Ongoing),
+            ("8/8/8/2n1k3/8/8/2N5/4K3 w - -", Status::Ongoing),
+            ("8/8/8/4k3/8/8/4P3/4K3 w - -", Status::Ongoing),
+        ] {
+            assert_eq!(status, Game::from_fen(fen.to_string()).unwrap().status());
+        }
+    }
+
+    #[test]
+    pub fn status_move_rules() {
+        for (fen, status) in [
+            ("8/8/8/4k3/8/8/8/R3K3 w - - 99 80", Status::Ongoing),
+            ("8/8/8/4k3/8/8/8/R3K3 w - - 100 80", Status::FiftyMoveRule),
+            (
+                "8/8/8/4k3/8/8/8/R3K3 w - - 150 105",
+                Status::SeventyFiveMove,
+            ),
+        ] {
+            assert_eq!(status, Game::from_fen(fen.to_string()).unwrap().status());
+        }
+    }
+
+    #[test]
+    pub fn status_repetition() {
+        let mut game = Game::new();
+
+        // Bring the knights out and back, returning to the start
+        let shuffle = [
+            Move::new(0x06, 0x25),
+            Move::new(0x76, 0x55),
+            Move::new(0x25, 0x06),
+            Move::new(0x55, 0x76),
+        ];
+        for (repetitions, status) in [
+            (2, Status::Ongoing),
+            (3, Status::ThreefoldRepetition),
+            (4, Status::ThreefoldRepetition),
+            (5, Status::FivefoldRepetition),
+        ] {
+            for m in shuffle {
+                game.do_move(m);
+            }
+            assert_eq!(repetitions, game.repetitions());
+            assert_eq!(status, game.status());
+        }
+
+        // Taking a move back forgets the position it left
+        let undo = game.do_move(shuffle[0]);
+        game.undo_move(shuffle[0], undo);
+        assert_eq!(16, game.history.len());
+        assert_eq!(5, game.repetitions());
     }
 }
@@ -0,0 +1,699 @@
+use std::fmt::Display;
+
+use magic::{bishop_attacks, rook_attacks};
+
+use super::{
+    fen::FenError,
+    perft::{Divide, PerftStats},
+    Game, Move, Piece, PieceList, Player, Position, Side, Space, Undo, CAPTURE, CASTLE,
+    DOUBLE_PUSH, EN_PASSANT,
+};
+
+pub(super) mod magic;
+
+// Squares here are numbered 0..64 from a1 to h8, so a square's bit is 1 << square
+// Moves still use 0x88 spaces, so they are converted at the edges
+pub(super) fn to_square(space: usize) -> usize {
+    (space >> 4) * 8 + (space & 0x07)
+}
+
+pub(super) fn to_space(square: usize) -> usize {
+    (square / 8) * 16 + square % 8
+}
+
+// Iterate the squares in a set, lowest first
+pub(super) fn squares(mut set: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if set == 0 {
+            return None;
+        }
+        let square = set.trailing_zeros() as usize;
+        set &= set - 1;
+        Some(square)
+    })
+}
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_B: u64 = FILE_A << 1;
+const FILE_G: u64 = FILE_A << 6;
+const FILE_H: u64 = FILE_A << 7;
+
+fn knight_attacks(square: usize) -> u64 {
+    let bit = 1u64 << square;
+    let one = ((bit >> 1) & !FILE_H) | ((bit << 1) & !FILE_A);
+    let two = ((bit >> 2) & !(FILE_G | FILE_H)) | ((bit << 2) & !(FILE_A | FILE_B));
+    (one << 16) | (one >> 16) | (two << 8) | (two >> 8)
+}
+
+fn king_attacks(square: usize) -> u64 {
+    let bit = 1u64 << square;
+    let row = bit | ((bit >> 1) & !FILE_H) | ((bit << 1) & !FILE_A);
+    (row | (row << 8) | (row >> 8)) & !bit
+}
+
+// The squares from a to b along a rank, including both
+fn span(a: usize, b: usize) -> u64 {
+    (u64::MAX >> (63 - a.max(b))) & (u64::MAX << a.min(b))
+}
+
+// The squares a pawn of the given side on this square attacks
+fn pawn_attacks(side: Side, square: usize) -> u64 {
+    let bit = 1u64 << square;
+    match side {
+        Side::White => ((bit << 7) & !FILE_H) | ((bit << 9) & !FILE_A),
+        Side::Black => ((bit >> 9) & !FILE_H) | ((bit >> 7) & !FILE_A),
+    }
+}
+
+// A position held as sets of squares for each kind of piece and each side
+// This is an alternative to the 0x88 Game, which stays as the reference it is checked against
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitboardGame {
+    pieces: [u64; 6],
+    sides: [u64; 2],
+    current_player: Side,
+    en_passant: Option<usize>, // The square skipped by a pawn double move on the last turn
+    halfmove_clock: usize,
+    fullmove_number: usize,
+    castling: [bool; 4],    // White kingside and queenside, then black
+    rook_files: [usize; 4], // The files of the rooks each of those castles with
+    check: [bool; 2],
+}
+
+impl From<&Game> for BitboardGame {
+    fn from(game: &Game) -> Self {
+        let mut new_game = BitboardGame {
+            pieces: [0; 6],
+            sides: [0; 2],
+            current_player: game.current_player,
+            en_passant: game.en_passant.map(to_square),
+            halfmove_clock: game.halfmove_clock,
+            fullmove_number: game.fullmove_number,
+            castling: [
+                game.white.castle_kingside,
+                game.white.castle_queenside,
+                game.black.castle_kingside,
+                game.black.castle_queenside,
+            ],
+            rook_files: [
+                game.white.rook_files[0],
+                game.white.rook_files[1],
+                game.black.rook_files[0],
+                game.black.rook_files[1],
+            ],
+            check: [game.white.check, game.black.check],
+        };
+        for (space, contents) in game.board.iter().enumerate() {
+            if let Some(Space { piece, side }) = contents {
+                new_game.put(*piece, *side, to_square(space));
+            }
+        }
+
+        new_game
+    }
+}
+
+impl From<&BitboardGame> for Game {
+    fn from(game: &BitboardGame) -> Self {
+        let mut board = [None; 128];
+        for square in 0..64 {
+            board[to_space(square)] = game.space(square);
+        }
+        let player = |side: Side| Player {
+            pieces: PieceList::from_board(&board, side),
+            check: game.check[side as usize],
+            castle_kingside: game.castling[side as usize * 2],
+            castle_queenside: game.castling[side as usize * 2 + 1],
+            rook_files: [
+                game.rook_files[side as usize * 2],
+                game.rook_files[side as usize * 2 + 1],
+            ],
+        };
+
+        let mut new_game = Game {
+            current_player: game.current_player,
+            en_passant: game.en_passant.map(to_space),
+            halfmove_clock: game.halfmove_clock,
+            fullmove_number: game.fullmove_number,
+            white: player(Side::White),
+            black: player(Side::Black),
+            occupied: Game::occupancy(&board),
+            board,
+            history: vec![],
+            key: 0,
+        };
+        new_game.key = new_game.compute_key();
+
+        new_game
+    }
+}
+
+impl Display for BitboardGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Game::from(self).fmt(f)
+    }
+}
+
+impl Default for BitboardGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BitboardGame {
+    pub fn new() -> Self {
+        BitboardGame::from(&Game::new())
+    }
+
+    pub fn from_fen(raw_game: String) -> Result<BitboardGame, FenError> {
+        Game::from_fen(raw_game).map(|game| BitboardGame::from(&game))
+    }
+
+    pub fn to_fen(&self) -> String {
+        Game::from(self).to_fen()
+    }
+
+    fn occupied(&self) -> u64 {
+        self.sides[0] | self.sides[1]
+    }
+
+    fn space(&self, square: usize) -> Option<Space> {
+        let bit = 1u64 << square;
+        let side = if self.sides[Side::White as usize] & bit != 0 {
+            Side::White
+        } else if self.sides[Side::Black as usize] & bit != 0 {
+            Side::Black
+        } else {
+            return None;
+        };
+        Piece::ALL
+            .into_iter()
+            .find(|piece| self.pieces[*piece as usize] & bit != 0)
+            .map(|piece| Space { piece, side })
+    }
+
+    fn put(&mut self, piece: Piece, side: Side, square: usize) {
+        self.pieces[piece as usize] |= 1 << square;
+        self.sides[side as usize] |= 1 << square;
+    }
+
+    fn take(&mut self, piece: Piece, side: Side, square: usize) {
+        self.pieces[piece as usize] &= !(1 << square);
+        self.sides[side as usize] &= !(1 << square);
+    }
+
+    fn king(&self, side: Side) -> usize {
+        (self.pieces[Piece::King as usize] & self.sides[side as usize]).trailing_zeros() as usize
+    }
+
+    // Is the space attacked by any piece of the given side?
+    pub fn is_attacked(&self, space: usize, by: Side) -> bool {
+        self.attacked(to_square(space), by)
+    }
+
+    // The spaces of every piece of the given side attacking the space, lowest first
+    pub fn attackers(&self, space: usize, by: Side) -> Vec<usize> {
+        squares(self.attacker_set(to_square(space), by))
+            .map(to_space)
+            .collect()
+    }
+
+    fn attacked(&self, square: usize, by: Side) -> bool {
+        self.attacker_set(square, by) != 0
+    }
+
+    fn attacker_set(&self, square: usize, by: Side) -> u64 {
+        let occupied = self.occupied();
+        let queens = self.pieces[Piece::Queen as usize];
+
+        (knight_attacks(square) & self.pieces[Piece::Knight as usize]
+            | king_attacks(square) & self.pieces[Piece::King as usize]
+            | pawn_attacks(!by, square) & self.pieces[Piece::Pawn as usize]
+            | rook_attacks(square, occupied) & (self.pieces[Piece::Rook as usize] | queens)
+            | bishop_attacks(square, occupied) & (self.pieces[Piece::Bishop as usize] | queens))
+            & self.sides[by as usize]
+    }
+
+    // Every legal move for the current player, found by playing out each pseudo-legal move and testing for check
+    pub fn generate_moves(&self) -> Vec<Move> {
+        let side = self.current_player;
+        let mut moves = vec![];
+        self.generate_pseudo_moves(&mut moves);
+
+        let mut scratch = self.clone();
+        moves.retain(|m| {
+            let undo = scratch.do_move(*m);
+            let legal = !scratch.check[side as usize];
+            scratch.undo_move(*m, undo);
+            legal
+        });
+
+        moves
+    }
+
+    fn generate_pseudo_moves(&self, moves: &mut Vec<Move>) {
+        let side = self.current_player;
+        let us = self.sides[side as usize];
+        let occupied = self.occupied();
+
+        for piece in Piece::ALL {
+            for src in squares(self.pieces[piece as usize] & us) {
+                let targets = match piece {
+                    Piece::King => king_attacks(src),
+                    Piece::Queen => rook_attacks(src, occupied) | bishop_attacks(src, occupied),
+                    Piece::Rook => rook_attacks(src, occupied),
+                    Piece::Knight => knight_attacks(src),
+                    Piece::Bishop => bishop_attacks(src, occupied),
+                    Piece::Pawn => {
+                        self.generate_pawn_moves(moves, src);
+                        continue;
+                    }
+                };
+                for dest in squares(targets & !us) {
+                    self.push_move(moves, src, dest, None, 0);
+                }
+            }
+        }
+
+        self.generate_castling_moves(moves);
+    }
+
+    fn generate_pawn_moves(&self, moves: &mut Vec<Move>, src: usize) {
+        let side = self.current_player;
+        let occupied = self.occupied();
+        let (forward, start_rank) = match side {
+            Side::White => (src + 8, 1),
+            Side::Black => (src - 8, 6),
+        };
+
+        if occupied & (1 << forward) == 0 {
+            self.push_pawn_move(moves, src, forward, 0);
+
+            if src / 8 == start_rank {
+                let double = match side {
+                    Side::White => forward + 8,
+                    Side::Black => forward - 8,
+                };
+                if occupied & (1 << double) == 0 {
+                    self.push_move(moves, src, double, None, DOUBLE_PUSH);
+                }
+            }
+        }
+
+        let attacks = pawn_attacks(side, src);
+        for dest in squares(attacks & self.sides[!side as usize]) {
+            self.push_pawn_move(moves, src, dest, 0);
+        }
+        if let Some(en_passant) = self.en_passant {
+            if attacks & (1 << en_passant) != 0 {
+                self.push_move(moves, src, en_passant, None, CAPTURE | EN_PASSANT);
+            }
+        }
+    }
+
+    fn generate_castling_moves(&self, moves: &mut Vec<Move>) {
+        let side = self.current_player;
+        if self.check[side as usize] {
+            return;
+        }
+
+        let king = self.king(side);
+        let home = king / 8 * 8;
+        for (right, king_dest, rook_dest) in
+            [(side as usize * 2, 6, 5), (side as usize * 2 + 1, 2, 3)]
+        {
+            if !self.castling[right] {
+                continue;
+            }
+            let (rook, king_dest, rook_dest) = (
+                home + self.rook_files[right],
+                home + king_dest,
+                home + rook_dest,
+            );
+
+            // The path rules are explained in Game::generate_king_moves
+            let path = span(king, king_dest) | span(rook, rook_dest);
+            if self.occupied() & path & !(1 << king | 1 << rook) != 0
+                || squares(span(king, king_dest) & !(1 << king))
+                    .any(|square| self.attacked(square, !side))
+            {
+                continue;
+            }
+
+            moves.push(Move {
+                from: to_space(king) as u8,
+                to: to_space(king_dest) as u8,
+                promotion: None,
+                flags: CASTLE,
+            });
+        }
+    }
+
+    // One move per promotion piece on the last rank
+    fn push_pawn_move(&self, moves: &mut Vec<Move>, src: usize, dest: usize, flags: u8) {
+        if !(8..56).contains(&dest) {
+            for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                self.push_move(moves, src, dest, Some(piece), flags);
+            }
+        } else {
+            self.push_move(moves, src, dest, None, flags);
+        }
+    }
+
+    fn push_move(
+        &self,
+        moves: &mut Vec<Move>,
+        src: usize,
+        dest: usize,
+        promotion: Option<Piece>,
+        mut flags: u8,
+    ) {
+        if self.occupied() & (1 << dest) != 0 {
+            flags |= CAPTURE;
+        }
+        moves.push(Move {
+            from: to_space(src) as u8,
+            to: to_space(dest) as u8,
+            promotion,
+            flags,
+        });
+    }
+
+    // Apply a legal move to a copy, as Game::make_move does
+    pub fn make_move(&self, m: Move) -> Option<BitboardGame> {
+        let m = m.find_in(self.generate_moves())?;
+        let mut new_game = self.clone();
+        new_game.do_move(m);
+
+        Some(new_game)
+    }
+
+    // The number of leaf positions depth moves from here
+    pub fn perft(&self, depth: usize) -> u64 {
+        super::perft::count(&mut self.clone(), depth)
+    }
+
+    // Split the perft count by the first move, as Game::divide does
+    pub fn divide(&self, depth: usize) -> Divide {
+        let game = Game::from(self);
+        super::perft::split(self, depth, |m| game.to_uci(m))
+    }
+
+    // What the moves at each ply up to depth did, for comparing against the published perft tables
+    pub fn perft_stats(&self, depth: usize) -> Vec<PerftStats> {
+        let mut levels = vec![PerftStats::default(); depth];
+        super::perft::collect(&mut levels, &mut self.clone());
+        levels
+    }
+
+    // Apply a move in place, with the same contract as Game::do_move
+    pub fn do_move(&mut self, m: Move) -> Undo {
+        let (src, dest) = (to_square(m.from()), to_square(m.to()));
+        let side = self.current_player;
+        let piece = self.space(src).unwrap().piece;
+
+        // Castling moves the rook around the king as Game::apply_move does
+        let castle = m.is_castle().then(|| self.castling_rook(side, dest));
+        if let Some((rook_src, _)) = castle {
+            self.take(Piece::Rook, side, rook_src);
+        }
+
+        let undo = Undo {
+            captured: self
+                .space(dest)
+                .filter(|space| space.side != side)
+                .map(|space| space.piece),
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            castling: self.castling,
+            check: self.check,
+        };
+
+        if let Some(captured) = undo.captured {
+            self.take(captured, !side, dest);
+        }
+        self.take(piece, side, src);
+        self.put(m.promotion().unwrap_or(piece), side, dest);
+
+        self.en_passant = None;
+        if piece == Piece::Pawn || undo.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if side == Side::Black {
+            self.fullmove_number += 1;
+        }
+
+        if let Piece::Pawn = piece {
+            if Some(dest) == undo.en_passant {
+                self.take(
+                    Piece::Pawn,
+                    !side,
+                    BitboardGame::en_passant_capture(side, dest),
+                );
+            }
+
+            if dest == src + 16 {
+                self.en_passant = Some(src + 8);
+            } else if dest + 16 == src {
+                self.en_passant = Some(dest + 8);
+            }
+        }
+
+        if let Some((_, rook_dest)) = castle {
+            self.put(Piece::Rook, side, rook_dest);
+        }
+
+        if piece == Piece::King {
+            self.castling[side as usize * 2] = false;
+            self.castling[side as usize * 2 + 1] = false;
+        }
+        for (right, home) in [(0, 0), (1, 0), (2, 56), (3, 56)] {
+            let rook = home + self.rook_files[right];
+            if src == rook || dest == rook {
+                self.castling[right] = false;
+            }
+        }
+
+        for side in [Side::White, Side::Black] {
+            self.check[side as usize] = self.attacked(self.king(side), !side);
+        }
+
+        self.current_player = !side;
+        undo
+    }
+
+    // Take back a move made by do_move
+    pub fn undo_move(&mut self, m: Move, undo: Undo) {
+        let (src, dest) = (to_square(m.from()), to_square(m.to()));
+        let side = !self.current_player;
+        let piece = self.space(dest).unwrap().piece;
+
+        let castle = m.is_castle().then(|| self.castling_rook(side, dest));
+        if let Some((_, rook_dest)) = castle {
+            self.take(Piece::Rook, side, rook_dest);
+        }
+
+        self.take(piece, side, dest);
+        self.put(
+            if m.promotion().is_some() {
+                Piece::Pawn
+            } else {
+                piece
+            },
+            side,
+            src,
+        );
+
+        if let Some((rook_src, _)) = castle {
+            self.put(Piece::Rook, side, rook_src);
+        }
+
+        if let Some(captured) = undo.captured {
+            self.put(captured, !side, dest);
+        } else if piece == Piece::Pawn && Some(dest) == undo.en_passant {
+            self.put(
+                Piece::Pawn,
+                !side,
+                BitboardGame::en_passant_capture(side, dest),
+            );
+        }
+
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        if side == Side::Black {
+            self.fullmove_number -= 1;
+        }
+        self.castling = undo.castling;
+        self.check = undo.check;
+
+        self.current_player = side;
+    }
+
+    // The square holding the pawn taken by an en passant capture landing on dest
+    fn en_passant_capture(side: Side, dest: usize) -> usize {
+        match side {
+            Side::White => dest - 8,
+            Side::Black => dest + 8,
+        }
+    }
+
+    // The squares the rook castles from and to, for a king castling onto dest
+    fn castling_rook(&self, side: Side, dest: usize) -> (usize, usize) {
+        let home = dest / 8 * 8;
+        if dest % 8 == 6 {
+            (home + self.rook_files[side as usize * 2], home + 5)
+        } else {
+            (home + self.rook_files[side as usize * 2 + 1], home + 3)
+        }
+    }
+}
+
+impl Position for BitboardGame {
+    fn from_fen(raw_game: String) -> Result<Self, FenError> {
+        BitboardGame::from_fen(raw_game)
+    }
+
+    fn to_fen(&self) -> String {
+        self.to_fen()
+    }
+
+    fn current_player(&self) -> Side {
+        self.current_player
+    }
+
+    fn in_check(&self) -> bool {
+        self.check[self.current_player as usize]
+    }
+
+    fn is_attacked(&self, space: usize, by: Side) -> bool {
+        self.is_attacked(space, by)
+    }
+
+    fn attackers(&self, space: usize, by: Side) -> Vec<usize> {
+        self.attackers(space, by)
+    }
+
+    fn checkers(&self) -> Vec<usize> {
+        let side = self.current_player;
+        self.attackers(to_space(self.king(side)), !side)
+    }
+
+    fn generate_moves(&self) -> Vec<Move> {
+        self.generate_moves()
+    }
+
+    fn make_move(&self, m: Move) -> Option<Self> {
+        self.make_move(m)
+    }
+
+    fn do_move(&mut self, m: Move) -> Undo {
+        self.do_move(m)
+    }
+
+    fn undo_move(&mut self, m: Move, undo: Undo) {
+        self.undo_move(m, undo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{test_game, TEST_POSITIONS};
+    use std::collections::HashSet;
+
+    #[test]
+    pub fn square_conversion_round_trips() {
+        for square in 0..64 {
+            assert_eq!(square, to_square(to_space(square)));
+        }
+        assert_eq!(0x00, to_space(0));
+        assert_eq!(0x34, to_space(28));
+        assert_eq!(0x77, to_space(63));
+    }
+
+    #[test]
+    pub fn fen_round_trips() {
+        for fen in TEST_POSITIONS {
+            let game = test_game(fen);
+            let bitboard = BitboardGame::from_fen(fen.to_string()).unwrap();
+
+            assert_eq!(game.to_fen(), bitboard.to_fen());
+            assert_eq!(game, Game::from(&bitboard));
+        }
+    }
+
+    #[test]
+    pub fn generate_moves_matches_game() {
+        for fen in TEST_POSITIONS {
+            let game = test_game(fen);
+            let bitboard = BitboardGame::from(&game);
+
+            let expected: HashSet<Move> = game.generate_moves().into_iter().collect();
+            let moves: HashSet<Move> = bitboard.generate_moves().into_iter().collect();
+            assert_eq!(expected, moves);
+
+            for m in moves {
+                let expected = game.make_move(m).unwrap();
+                assert_eq!(
+                    expected.without_history(),
+                    Game::from(&bitboard.make_move(m).unwrap())
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn undo_move_restores_game() {
+        for fen in TEST_POSITIONS {
+            let mut game = BitboardGame::from(&test_game(fen));
+            let original = game.clone();
+
+            for m in original.generate_moves() {
+                let undo = game.do_move(m);
+                for reply in game.generate_moves() {
+                    let reply_undo = game.do_move(reply);
+                    game.undo_move(reply, reply_undo);
+                }
+                game.undo_move(m, undo);
+                assert_eq!(original, game);
+            }
+        }
+    }
+
+    #[test]
+    pub fn attackers_match_game() {
+        for fen in TEST_POSITIONS {
+            let game = test_game(fen);
+            let bitboard = BitboardGame::from(&game);
+
+            for square in 0..64 {
+                for side in [Side::White, Side::Black] {
+                    let mut expected = game.attackers(to_space(square), side);
+                    expected.sort();
+                    assert_eq!(expected, bitboard.attackers(to_space(square), side));
+                    assert_eq!(
+                        game.is_attacked(to_space(square), side),
+                        bitboard.is_attacked(to_space(square), side)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn attack_sets() {
+        // Knight and king in the corner, pawns on the edge
+        assert_eq!((1 << 10) | (1 << 17), knight_attacks(0));
+        assert_eq!((1 << 1) | (1 << 8) | (1 << 9), king_attacks(0));
+        assert_eq!(1 << 49, pawn_attacks(Side::White, 40));
+        assert_eq!(1 << 30, pawn_attacks(Side::Black, 39));
+
+        // A rook on a1 stopped by a piece on a4
+        assert_eq!(
+            0xfe | (1 << 8) | (1 << 16) | (1 << 24),
+            rook_attacks(0, 1 << 24)
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::OnceLock;
+
+// File and rank steps for the sliding pieces
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+// A seed for each rank, fixed so the tables, and how long they take to find, are the same every run
+// These are the seeds Stockfish uses, which find every magic quickly with this generator
+const SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+// Walk each ray from the square until it leaves the board or hits a piece, which is included
+// This is only used to fill the tables, lookups go through the magics below
+fn sliding_attacks(square: usize, occupied: u64, directions: &[(i8, i8); 4]) -> u64 {
+    let mut attacks = 0;
+    for (file_step, rank_step) in directions {
+        let (mut file, mut rank) = ((square % 8) as i8, (square / 8) as i8);
+        loop {
+            file += file_step;
+            rank += rank_step;
+            if !(0..8).contains(&file) || !(0..8).contains(&rank) {
+                break;
+            }
+            let bit = 1u64 << (rank * 8 + file);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+        }
+    }
+
+    attacks
+}
+
+// The squares whose pieces can block a ray from the square
+// The last square on each ray is left out, since nothing lies behind it to block
+fn blocker_mask(square: usize, directions: &[(i8, i8); 4]) -> u64 {
+    let mut mask = 0;
+    for (file_step, rank_step) in directions {
+        let (mut file, mut rank) = (
+            (square % 8) as i8 + file_step,
+            (square / 8) as i8 + rank_step,
+        );
+        while (0..8).contains(&(file + file_step)) && (0..8).contains(&(rank + rank_step)) {
+            mask |= 1 << (rank * 8 + file);
+            file += file_step;
+            rank += rank_step;
+        }
+    }
+
+    mask
+}
+
+// xorshift64*, good enough to find magics without pulling in a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Magics with few bits set are found much faster
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
+
+// Multiplying the blockers on a square's mask by its magic gives a unique index into its slice of the table
+#[derive(Clone, Copy, Debug, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct Magics {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+impl Magics {
+    fn generate() -> Self {
+        let mut attacks = vec![];
+        let rook = Magics::generate_piece(&mut attacks, &ROOK_DIRECTIONS);
+        let bishop = Magics::generate_piece(&mut attacks, &BISHOP_DIRECTIONS);
+
+        Magics {
+            rook,
+            bishop,
+            attacks,
+        }
+    }
+
+    fn generate_piece(attacks: &mut Vec<u64>, directions: &[(i8, i8); 4]) -> [Magic; 64] {
+        let mut magics = [Magic::default(); 64];
+
+        for (square, magic) in magics.iter_mut().enumerate() {
+            let mask = blocker_mask(square, directions);
+            let bits = mask.count_ones();
+
+            // Every arrangement of blockers on the mask, with the attacks it leaves
+            let mut blockers = vec![];
+            let mut subset = 0u64;
+            loop {
+                blockers.push((subset, sliding_attacks(square, subset, directions)));
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+
+            // Try candidates until one maps every arrangement without a clash
+            // Clashes are fine when the attacks are the same, which is what makes the table small
+            let mut rng = Rng(SEEDS[square / 8]);
+            let mut table = vec![0u64; 1 << bits];
+            let mut used = vec![0usize; 1 << bits];
+            for attempt in 1.. {
+                *magic = Magic {
+                    mask,
+                    magic: rng.sparse(),
+                    shift: 64 - bits,
+                    offset: 0,
+                };
+                if (mask.wrapping_mul(magic.magic) >> 56).count_ones() < 6 {
+                    continue;
+                }
+
+                let found = blockers.iter().all(|(blockers, attack)| {
+                    let index = magic.index(*blockers);
+                    if used[index] != attempt {
+                        used[index] = attempt;
+                        table[index] = *attack;
+                        true
+                    } else {
+                        table[index] == *attack
+                    }
+                });
+                if found {
+                    break;
+                }
+            }
+
+            magic.offset = attacks.len();
+            attacks.extend(table);
+        }
+
+        magics
+    }
+}
+
+// Found on the first lookup, then shared for the rest of the run
+static MAGICS: OnceLock<Magics> = OnceLock::new();
+
+fn magics() -> &'static Magics {
+    MAGICS.get_or_init(Magics::generate)
+}
+
+pub fn rook_attacks(square: usize, occupied: u64) -> u64 {
+    let magics = magics();
+    magics.attacks[magics.rook[square].index(occupied)]
+}
+
+pub fn bishop_attacks(square: usize, occupied: u64) -> u64 {
+    let magics = magics();
+    magics.attacks[magics.bishop[square].index(occupied)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn blocker_masks_leave_out_edges() {
+        // A rook in the corner can be blocked on six squares each way
+        assert_eq!(12, blocker_mask(0, &ROOK_DIRECTIONS).count_ones());
+        assert_eq!(10, blocker_mask(27, &ROOK_DIRECTIONS).count_ones());
+        assert_eq!(6, blocker_mask(0, &BISHOP_DIRECTIONS).count_ones());
+        assert_eq!(9, blocker_mask(27, &BISHOP_DIRECTIONS).count_ones());
+    }
+
+    #[test]
+    pub fn lookups_match_ray_walk() {
+        let mut rng = Rng(1);
+        for square in 0..64 {
+            for _ in 0..200 {
+                let occupied = rng.sparse() | rng.sparse();
+                assert_eq!(
+                    sliding_attacks(square, occupied, &ROOK_DIRECTIONS),
+                    rook_attacks(square, occupied)
+                );
+                assert_eq!(
+                    sliding_attacks(square, occupied, &BISHOP_DIRECTIONS),
+                    bishop_attacks(square, occupied)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::{error::Error, fmt::Display};
+
+use super::{
+    fen::{fields, FenError},
+    san::SanError,
+    Game, Move,
+};
+
+// Why an EPD line was rejected, offsets are in bytes from the start of the line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EpdError {
+    Fen(FenError), // The position fields start the line, so their offsets are also from the start of it
+    UnexpectedOpcode { offset: usize },
+    UnexpectedOperand { offset: usize },
+    UnterminatedString { offset: usize },
+}
+
+impl Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::Fen(error) => error.fmt(f),
+            EpdError::UnexpectedOpcode { offset } => {
+                write!(f, "unexpected opcode at offset {}", offset)
+            }
+            EpdError::UnexpectedOperand { offset } => {
+                write!(f, "unexpected operand at offset {}", offset)
+            }
+            EpdError::UnterminatedString { offset } => {
+                write!(f, "string at offset {} is never closed", offset)
+            }
+        }
+    }
+}
+
+impl Error for EpdError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EpdError::Fen(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// A position from an Extended Position Description line, with the operations that came with it,
+// like the best move "bm", the name "id" or the perft counts "D1" to "D6"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Epd {
+    game: Game,
+    operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    pub fn new(game: Game) -> Self {
+        Epd {
+            game,
+            operations: vec![],
+        }
+    }
+
+    pub fn with_operation(mut self, opcode: &str, operands: &[&str]) -> Self {
+        self.operations.push((
+            opcode.to_string(),
+            operands.iter().map(|operand| operand.to_string()).collect(),
+        ));
+        self
+    }
+
+    // Parse a line of the position's four FEN fields followed by operations, each an opcode and its
+    // operands ended by a semicolon
+    // Perft suites that put the semicolon before each operation, or keep FEN's clocks, are read too
+    pub fn parse(line: &str) -> Result<Epd, EpdError> {
+        let line = line.trim_end();
+        // The position's fields can be split by any whitespace, as from_fen reads them
+        let mut fields = fields(line).map(|(start, field)| (start + field.len(), field));
+        let mut end = fields.by_ref().take(4).last().map_or(0, |(end, _)| end);
+        let mut game = Game::from_fen(line[..end].to_string()).map_err(EpdError::Fen)?;
+
+        let mut clock = || {
+            fields
+                .next()
+                .and_then(|(end, field)| Some((field.parse::<usize>().ok()?, end)))
+        };
+        if let Some((halfmove_clock, _)) = clock() {
+            if let Some((fullmove_number, fullmove_end)) = clock() {
+                game.halfmove_clock = halfmove_clock;
+                game.fullmove_number = fullmove_number;
+                end = fullmove_end;
+            }
+        }
+
+        let mut epd = Epd {
+            game,
+            operations: vec![],
+        };
+        let mut runes = line.char_indices().skip_while(|(i, _)| *i < end).peekable();
+        loop {
+            while runes
+                .next_if(|(_, rune)| rune.is_whitespace() || *rune == ';')
+                .is_some()
+            {}
+            let Some((offset, _)) = runes.peek().copied() else {
+                break;
+            };
+
+            // Opcodes start with a letter, followed by letters, digits and underscores
+            let mut opcode = String::new();
+            while let Some((_, rune)) =
+                runes.next_if(|(_, rune)| !rune.is_whitespace() && *rune != ';')
+            {
+                opcode.push(rune);
+            }
+            if !opcode.starts_with(|rune: char| rune.is_ascii_alphabetic())
+                || !opcode
+                    .chars()
+                    .all(|rune| rune.is_ascii_alphanumeric() || rune == '_')
+            {
+                return Err(EpdError::UnexpectedOpcode { offset });
+            }
+
+            // Operands run up to the semicolon, strings are quoted and can hold spaces and semicolons, with
+            // \" and \\ escaped as in PGN tags
+            let mut operands = vec![];
+            loop {
+                while runes.next_if(|(_, rune)| rune.is_whitespace()).is_some() {}
+                let Some((start, rune)) = runes.next() else {
+                    break;
+                };
+                match rune {
+                    ';' => break,
+                    '"' => {
+                        let mut operand = String::new();
+                        loop {
+                            match runes.next() {
+                                Some((_, '"')) => break,
+                                Some((_, '\\')) => match runes.next() {
+                                    Some((_, rune)) => operand.push(rune),
+                                    None => {
+                                        return Err(EpdError::UnterminatedString { offset: start })
+                                    }
+                                },
+                                Some((_, rune)) => operand.push(rune),
+                                None => return Err(EpdError::UnterminatedString { offset: start }),
+                            }
+                        }
+                        operands.push(operand);
+                    }
+                    _ => {
+                        let mut operand = rune.to_string();
+                        while let Some((_, rune)) =
+                            runes.next_if(|(_, rune)| !rune.is_whitespace() && *rune != ';')
+                        {
+                            operand.push(rune);
+                        }
+
+                        // The clocks and perft counts have to be numbers
+                        let numeric = matches!(opcode.as_str(), "hmvc" | "fmvn")
+                            || (opcode.len() == 2 && opcode.starts_with('D'));
+                        let number = operand.parse::<u64>();
+                        match (numeric, number) {
+                            (true, Err(_)) => {
+                                return Err(EpdError::UnexpectedOperand { offset: start })
+                            }
+                            (true, Ok(number)) if opcode == "hmvc" => {
+                                epd.game.halfmove_clock = number as usize
+                            }
+                            (true, Ok(number)) if opcode == "fmvn" => {
+                                epd.game.fullmove_number = number as usize
+                            }
+                            _ => {}
+                        }
+                        operands.push(operand);
+                    }
+                }
+            }
+            epd.operations.push((opcode, operands));
+        }
+
+        Ok(epd)
+    }
+
+    // Write out the position's four fields and the operations, quoting operands that need it
+    pub fn to_epd(&self) -> String {
+        let fen = self.game.to_fen();
+        let mut line = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+
+        for (opcode, operands) in &self.operations {
+            line.push(' ');
+            line.push_str(opcode);
+            for operand in operands {
+                let quote = opcode == "id"
+                    || (opcode.len() == 2 && opcode.starts_with('c'))
+                    || operand.is_empty()
+                    || operand
+                        .contains(|rune: char| rune.is_whitespace() || rune == ';' || rune == '"');
+                if quote {
+                    let operand = operand.replace('\\', "\\\\").replace('"', "\\\"");
+                    line.push_str(&format!(" \"{}\"", operand));
+                } else {
+                    line.push(' ');
+                    line.push_str(operand);
+                }
+            }
+            line.push(';');
+        }
+
+        line
+    }
+
+    // The position, with its clocks set from hmvc and fmvn if they were given
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn operations(&self) -> &[(String, Vec<String>)] {
+        &self.operations
+    }
+
+    // The operands of the first operation with this opcode
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(code, _)| code == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operands("id")?.first().map(String::as_str)
+    }
+
+    // The moves a solver should find, given in SAN
+    pub fn best_moves(&self) -> Result<Vec<Move>, SanError> {
+        self.moves("bm")
+    }
+
+    // The moves a solver should not play, given in SAN
+    pub fn avoid_moves(&self) -> Result<Vec<Move>, SanError> {
+        self.moves("am")
+    }
+
+    // The predicted line, each move played from the position after the one before it
+    pub fn pv(&self) -> Result<Vec<Move>, SanError> {
+        let mut game = self.game.clone();
+        self.operands("pv")
+            .unwrap_or_default()
+            .iter()
+            .map(|san| {
+                let m = game.parse_san(san)?;
+                game.do_move(m);
+                Ok(m)
+            })
+            .collect()
+    }
+
+    // The number of leaf positions at a depth, from the D1 to D6 operations
+    pub fn perft(&self, depth: usize) -> Option<u64> {
+        self.operands(&format!("D{}", depth))?.first()?.parse().ok()
+    }
+
+    fn moves(&self, opcode: &str) -> Result<Vec<Move>, SanError> {
+        self.operands(opcode)
+            .unwrap_or_default()
+            .iter()
+            .map(|san| self.game.parse_san(san))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn parse_epd_with_operations() {
+        let epd = Epd::parse(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - \
+             bm Bb5 Bc4; am g4; id \"Open game; 3rd move\"; c0 \"A comment\"; hmvc 2; fmvn 3;",
+        )
+        .unwrap();
+
+        assert_eq!(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            epd.game().to_fen()
+        );
+        assert_eq!(6, epd.operations().len());
+        assert_eq!(Some("Open game; 3rd move"), epd.id());
+        assert_eq!(Some(&["A comment".to_string()][..]), epd.operands("c0"));
+        assert_eq!(
+            Ok(vec![0x05, 0x05]),
+            epd.best_moves()
+                .map(|moves| moves.iter().map(|m| m.from()).collect())
+        );
+        assert_eq!(Ok(1), epd.avoid_moves().map(|moves| moves.len()));
+        assert_eq!(Ok(vec![]), epd.pv());
+        assert_eq!(None, epd.perft(1));
+    }
+
+    #[test]
+    pub fn parse_perft_suites() {
+        // Semicolons before each operation, with and without FEN's clocks
+        for line in [
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - ;D1 26 ;D2 568 ;D3 13744",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 ;D1 26 ;D2 568 ;D3 13744",
+            // Doubled spaces and tabs between the fields
+            "r3k2r/8/8/8/8/8/8/R3K2R  w\tKQkq -\t;D1 26 ;D2 568 ;D3 13744",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq  -  0\t1 ;D1 26 ;D2 568 ;D3 13744",
+        ] {
+            let epd = Epd::parse(line).unwrap();
+            assert_eq!(Some(26), epd.perft(1));
+            assert_eq!(Some(13744), epd.perft(3));
+            assert_eq!(None, epd.perft(4));
+            assert_eq!(
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - D1 26; D2 568; D3 13744;",
+                epd.to_epd()
+            );
+        }
+    }
+
+    #[test]
+    pub fn parse_epd_pv() {
+        let epd = Epd::parse("6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - pv h6 Ra8+ Kh7;").unwrap();
+        assert_eq!(Ok(3), epd.pv().map(|pv| pv.len()));
+
+        let epd = Epd::parse("6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - pv h6 Ra8+ Kg8;").unwrap();
+        assert_eq!(Err(SanError::IllegalMove), epd.pv());
+    }
+
+    #[test]
+    pub fn epd_round_trips() {
+        let epd = Epd::new(Game::new())
+            .with_operation("id", &["start"])
+            .with_operation("bm", &["e4", "d4"])
+            .with_operation("c1", &["two words"]);
+        let line = epd.to_epd();
+
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"start\"; bm e4 d4; c1 \"two words\";",
+            line
+        );
+        assert_eq!(epd, Epd::parse(&line).unwrap());
+
+        // Quotes and backslashes inside a string are escaped
+        let epd = Epd::new(Game::new())
+            .with_operation("id", &["the \"open\" game"])
+            .with_operation("c0", &["a\\b\\"])
+            .with_operation("c1", &["6\""]);
+        let line = epd.to_epd();
+
+        assert!(line.ends_with(" id \"the \\\"open\\\" game\"; c0 \"a\\\\b\\\\\"; c1 \"6\\\"\";"));
+        assert_eq!(epd, Epd::parse(&line).unwrap());
+    }
+
+    #[test]
+    pub fn parse_epd_rejects_bad_lines() {
+        assert_eq!(
+            Err(EpdError::Fen(FenError::UnexpectedSide { offset: 44 })),
+            Epd::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - bm e4;")
+        );
+        assert_eq!(
+            Err(EpdError::UnexpectedOpcode { offset: 53 }),
+            Epd::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 1bm e4;")
+        );
+        assert_eq!(
+            Err(EpdError::UnexpectedOperand { offset: 56 }),
+            Epd::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - D1 twenty;")
+        );
+        assert_eq!(
+            Err(EpdError::UnterminatedString { offset: 56 }),
+            Epd::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"open")
+        );
+        assert_eq!(
+            Err(EpdError::UnterminatedString { offset: 56 }),
+            Epd::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"open\\\"")
+        );
+    }
+}
@@ -0,0 +1,756 @@
+use std::{error::Error, fmt::Display};
+
+use super::{parse_space, space_name, Game, Piece, PieceList, Player, Side, Space, MAX_PIECES, UP};
+
+// Why a FEN string was rejected, offsets are in bytes from the start of the string
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FenError {
+    UnexpectedPiece { offset: usize, rune: char },
+    TooManyPieces { offset: usize },
+    PawnOnBackRank { offset: usize },
+    RankOverflow { offset: usize },
+    RankUnderflow { offset: usize },
+    WrongRankCount { ranks: usize },
+    MissingKing(Side),
+    ExtraKing(Side),
+    OpponentInCheck(Side), // The side that just moved was left in check
+    UnexpectedSide { offset: usize },
+    UnexpectedCastling { offset: usize, rune: char },
+    UnexpectedEnPassant { offset: usize },
+    UnexpectedHalfmoveClock { offset: usize },
+    UnexpectedFullmoveNumber { offset: usize },
+    UnexpectedField { offset: usize },
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::UnexpectedPiece { offset, rune } => {
+                write!(f, "unexpected piece '{}' at offset {}", rune, offset)
+            }
+            FenError::TooManyPieces { offset } => {
+                write!(
+                    f,
+                    "more pieces than promoting pawns could give at offset {}",
+                    offset
+                )
+            }
+            FenError::PawnOnBackRank { offset } => {
+                write!(f, "pawn on the first or last rank at offset {}", offset)
+            }
+            FenError::RankOverflow { offset } => {
+                write!(f, "rank has more than 8 spaces at offset {}", offset)
+            }
+            FenError::RankUnderflow { offset } => {
+                write!(f, "rank has fewer than 8 spaces at offset {}", offset)
+            }
+            FenError::WrongRankCount { ranks } => write!(f, "expected 8 ranks, found {}", ranks),
+            FenError::MissingKing(side) => write!(f, "{:?} has no king", side),
+            FenError::ExtraKing(side) => write!(f, "{:?} has more than one king", side),
+            FenError::OpponentInCheck(side) => {
+                write!(f, "{:?} is in check but it isn't their move", side)
+            }
+            FenError::UnexpectedSide { offset } => {
+                write!(f, "unexpected side to move at offset {}", offset)
+            }
+            FenError::UnexpectedCastling { offset, rune } => {
+                write!(
+                    f,
+                    "unexpected castling right '{}' at offset {}",
+                    rune, offset
+                )
+            }
+            FenError::UnexpectedEnPassant { offset } => {
+                write!(f, "unexpected en passant space at offset {}", offset)
+            }
+            FenError::UnexpectedHalfmoveClock { offset } => {
+                write!(f, "unexpected halfmove clock at offset {}", offset)
+            }
+            FenError::UnexpectedFullmoveNumber { offset } => {
+                write!(f, "unexpected fullmove number at offset {}", offset)
+            }
+            FenError::UnexpectedField { offset } => {
+                write!(f, "unexpected field at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl Error for FenError {}
+
+// The fields of a FEN with their offsets, split by any run of whitespace so pasted strings with doubled
+// spaces or a trailing newline still read
+pub(super) fn fields(raw_game: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut end = 0;
+    std::iter::from_fn(move || {
+        let start = end + raw_game[end..].find(|rune: char| !rune.is_whitespace())?;
+        end = raw_game[start..]
+            .find(char::is_whitespace)
+            .map_or(raw_game.len(), |length| start + length);
+        Some((start, &raw_game[start..end]))
+    })
+}
+
+impl Game {
+    // Create a new game object, using A Forsyth–Edwards Notation string
+    pub fn from_fen(raw_game: String) -> Result<Game, FenError> {
+        let mut game = Game {
+            board: [None; 128],
+            occupied: 0,
+            white: Player {
+                pieces: PieceList::new(),
+                check: false,
+                castle_kingside: false,
+                castle_queenside: false,
+                rook_files: [7, 0],
+            },
+            black: Player {
+                pieces: PieceList::new(),
+                check: false,
+                castle_kingside: false,
+                castle_queenside: false,
+                rook_files: [7, 0],
+            },
+            current_player: Side::White,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: vec![],
+            key: 0,
+        };
+
+        // Pair each field with its offset, so errors can point at it
+        let mut fen = fields(&raw_game);
+
+        let (_, placement) = fen.next().unwrap_or((0, ""));
+        let mut rank = 7;
+        let mut file = 0;
+        // Reported once the ranks are known to be right
+        let mut back_rank_pawn = None;
+        let mut too_many = None;
+        for (offset, rune) in placement.char_indices() {
+            let (piece, side) = match rune {
+                'P' => (Piece::Pawn, Side::White),
+                'N' => (Piece::Knight, Side::White),
+                'B' => (Piece::Bishop, Side::White),
+                'R' => (Piece::Rook, Side::White),
+                'Q' => (Piece::Queen, Side::White),
+                'K' => (Piece::King, Side::White),
+                'p' => (Piece::Pawn, Side::Black),
+                'n' => (Piece::Knight, Side::Black),
+                'b' => (Piece::Bishop, Side::Black),
+                'r' => (Piece::Rook, Side::Black),
+                'q' => (Piece::Queen, Side::Black),
+                'k' => (Piece::King, Side::Black),
+                '1'..='8' => {
+                    file += rune as usize - '0' as usize;
+                    if file > 8 {
+                        return Err(FenError::RankOverflow { offset });
+                    }
+                    continue;
+                }
+                '/' => {
+                    if file < 8 {
+                        return Err(FenError::RankUnderflow { offset });
+                    }
+                    if rank == 0 {
+                        return Err(FenError::WrongRankCount {
+                            ranks: placement.matches('/').count() + 1,
+                        });
+                    }
+                    rank -= 1;
+                    file = 0;
+                    continue;
+                }
+                _ => return Err(FenError::UnexpectedPiece { offset, rune }),
+            };
+            if file >= 8 {
+                return Err(FenError::RankOverflow { offset });
+            }
+
+            if piece == Piece::Pawn && (rank == 0 || rank == 7) {
+                back_rank_pawn = back_rank_pawn.or(Some(offset));
+            }
+            // Anything past the starting set has to have been a pawn, so a side's pawns and promoted pieces
+            // come to at most eight, which also keeps each kind within its piece list
+            // Extra kings are reported below, they only need to fit in the list
+            let pieces = &game.get_side(side).pieces;
+            let count = |kind: Piece| pieces.spaces(kind).count() + (kind == piece) as usize;
+            let promoted: usize = [
+                (Piece::Knight, 2),
+                (Piece::Bishop, 2),
+                (Piece::Rook, 2),
+                (Piece::Queen, 1),
+            ]
+            .into_iter()
+            .map(|(kind, start)| count(kind).saturating_sub(start))
+            .sum();
+            let possible = match piece {
+                Piece::King => count(Piece::King) <= MAX_PIECES,
+                _ => count(Piece::Pawn) + promoted <= 8,
+            };
+            if !possible {
+                too_many = too_many.or(Some(offset));
+                file += 1;
+                continue;
+            }
+
+            let space = rank * UP + file;
+            game.set_space(space, Some(Space { piece, side }));
+            game.get_side_mut(side).pieces.add(piece, space);
+            file += 1;
+        }
+        if file < 8 {
+            return Err(FenError::RankUnderflow {
+                offset: placement.len(),
+            });
+        }
+        if rank > 0 {
+            return Err(FenError::WrongRankCount { ranks: 8 - rank });
+        }
+        if let Some(offset) = too_many {
+            return Err(FenError::TooManyPieces { offset });
+        }
+        if let Some(offset) = back_rank_pawn {
+            return Err(FenError::PawnOnBackRank { offset });
+        }
+
+        for side in [Side::White, Side::Black] {
+            match game.get_side(side).pieces.spaces(Piece::King).count() {
+                0 => return Err(FenError::MissingKing(side)),
+                1 => {}
+                _ => return Err(FenError::ExtraKing(side)),
+            }
+        }
+
+        game.current_player = match fen.next() {
+            Some((_, "w")) | None => Side::White,
+            Some((_, "b")) => Side::Black,
+            Some((offset, _)) => return Err(FenError::UnexpectedSide { offset }),
+        };
+
+        if let Some((start, castling)) = fen.next() {
+            for (offset, rune) in castling.char_indices() {
+                if rune == '-' && castling.len() == 1 {
+                    continue;
+                }
+                let side = if rune.is_ascii_uppercase() {
+                    Side::White
+                } else {
+                    Side::Black
+                };
+                let home = match side {
+                    Side::White => 0x00,
+                    Side::Black => 0x70,
+                };
+                let king = game.get_side(side).pieces.king();
+                let is_rook = |space: &usize| {
+                    game.board[*space]
+                        == Some(Space {
+                            piece: Piece::Rook,
+                            side,
+                        })
+                };
+
+                // The king must be on its home rank with a rook to castle with on the same rank
+                // K and Q take the outermost rook on that side of the king, as in X-FEN, while a file names
+                // its rook directly, as in Shredder-FEN
+                let rook = match rune.to_ascii_lowercase() {
+                    _ if king & 0x70 != home => None,
+                    'k' => (king + 1..home + 8).rev().find(is_rook),
+                    'q' => (home..king).find(is_rook),
+                    file @ 'a'..='h' => Some(home + file as usize - 'a' as usize).filter(is_rook),
+                    _ => None,
+                }
+                .ok_or(FenError::UnexpectedCastling {
+                    offset: start + offset,
+                    rune,
+                })?;
+
+                let player = game.get_side_mut(side);
+                if rook > king {
+                    player.castle_kingside = true;
+                    player.rook_files[0] = rook & 0x07;
+                } else {
+                    player.castle_queenside = true;
+                    player.rook_files[1] = rook & 0x07;
+                }
+            }
+        }
+
+        if let Some((offset, en_passant)) = fen.next() {
+            if en_passant != "-" {
+                let space =
+                    parse_space(en_passant).ok_or(FenError::UnexpectedEnPassant { offset })?;
+
+                // The skipped space is empty, and behind a pawn that has just moved two spaces
+                let (rank, side) = match game.current_player {
+                    Side::White => (5, Side::Black),
+                    Side::Black => (2, Side::White),
+                };
+                if space >> 4 != rank {
+                    return Err(FenError::UnexpectedEnPassant { offset });
+                }
+                let pawn = match side {
+                    Side::White => space + UP,
+                    Side::Black => space - UP,
+                };
+                if game.board[space].is_some()
+                    || game.board[pawn]
+                        != Some(Space {
+                            piece: Piece::Pawn,
+                            side,
+                        })
+                {
+                    return Err(FenError::UnexpectedEnPassant { offset });
+                }
+
+                game.en_passant = Some(space);
+            }
+        }
+
+        // The clocks are often left off, as in EPD
+        if let Some((offset, clock)) = fen.next() {
+            game.halfmove_clock = clock
+                .parse()
+                .map_err(|_| FenError::UnexpectedHalfmoveClock { offset })?;
+        }
+        if let Some((offset, number)) = fen.next() {
+            game.fullmove_number = number
+                .parse()
+                .map_err(|_| FenError::UnexpectedFullmoveNumber { offset })?;
+        }
+        if let Some((offset, _)) = fen.next() {
+            return Err(FenError::UnexpectedField { offset });
+        }
+
+        game.white.check = game.king_check(Side::White, game.white.pieces.king());
+        game.black.check = game.king_check(Side::Black, game.black.pieces.king());
+        if game.get_side(!game.current_player).check {
+            return Err(FenError::OpponentInCheck(!game.current_player));
+        }
+        game.key = game.compute_key();
+        Ok(game)
+    }
+
+    // Write out the game as a Forsyth–Edwards Notation string
+    // Castling rights are written as in X-FEN, which is plain FEN unless a Chess960 game has two rooks
+    // on one side of the king
+    pub fn to_fen(&self) -> String {
+        self.write_fen(false)
+    }
+
+    // Write out the game as FEN with castling rights given by the rooks' files, as Shredder-FEN does
+    pub fn to_shredder_fen(&self) -> String {
+        self.write_fen(true)
+    }
+
+    fn write_fen(&self, shredder: bool) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.board[rank * UP + file] {
+                    Some(space) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push_str(&space.to_string());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.current_player {
+            Side::White => " w ",
+            Side::Black => " b ",
+        });
+
+        let mut castling = String::new();
+        for (side, home) in [(Side::White, 0x00), (Side::Black, 0x70)] {
+            let player = self.get_side(side);
+            let [kingside, queenside] = player.rook_files;
+            for (allowed, file, rune, mut outside) in [
+                (player.castle_kingside, kingside, 'K', kingside + 1..8),
+                (player.castle_queenside, queenside, 'Q', 0..queenside),
+            ] {
+                if !allowed {
+                    continue;
+                }
+
+                // X-FEN only needs the file when another rook is further out, where K or Q would mean that one
+                let rune = if shredder
+                    || outside.any(|file| {
+                        self.board[home + file]
+                            == Some(Space {
+                                piece: Piece::Rook,
+                                side,
+                            })
+                    }) {
+                    (b'A' + file as u8) as char
+                } else {
+                    rune
+                };
+                castling.push(match side {
+                    Side::White => rune,
+                    Side::Black => rune.to_ascii_lowercase(),
+                });
+            }
+        }
+        if castling.is_empty() {
+            fen.push('-');
+        } else {
+            fen.push_str(&castling);
+        }
+
+        match self.en_passant {
+            Some(space) => fen.push_str(&format!(" {} ", space_name(space))),
+            None => fen.push_str(" - "),
+        }
+
+        fen.push_str(&format!("{} {}", self.halfmove_clock, self.fullmove_number));
+        fen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{test_game, Move, TEST_POSITIONS};
+
+    #[test]
+    pub fn parse_fen_matches_default() {
+        let game = Game::new();
+        let game_fen =
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0".to_string())
+                .unwrap();
+
+        assert_eq!(game.board, game_fen.board);
+    }
+
+    #[test]
+    pub fn parse_fen_with_inspection() {
+        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -".to_string()).unwrap();
+
+        assert!(game.black.pieces.contains(Piece::Pawn, 0x62));
+        assert!(game.black.pieces.contains(Piece::Pawn, 0x53));
+        assert_eq!(game.white.pieces.king(), 0x40);
+        assert!(game.white.pieces.contains(Piece::Pawn, 0x41));
+        assert!(game.black.pieces.contains(Piece::Rook, 0x47));
+        assert!(game.white.pieces.contains(Piece::Rook, 0x31));
+        assert!(game.black.pieces.contains(Piece::Pawn, 0x35));
+        assert_eq!(game.black.pieces.king(), 0x37);
+        assert!(game.white.pieces.contains(Piece::Pawn, 0x14));
+        assert!(game.white.pieces.contains(Piece::Pawn, 0x16));
+
+        assert_eq!(5, game.white.pieces.len());
+        assert_eq!(5, game.black.pieces.len());
+    }
+
+    #[test]
+    pub fn parse_fen_with_extra_material() {
+        let game = Game::from_fen("k7/8/8/8/8/8/NNN5/QQK5 w - -".to_string()).unwrap();
+
+        assert_eq!(2, game.white.pieces.spaces(Piece::Queen).count());
+        assert_eq!(3, game.white.pieces.spaces(Piece::Knight).count());
+        assert!(game.white.pieces.contains(Piece::Knight, 0x12));
+        assert_eq!(6, game.white.pieces.len());
+    }
+
+    #[test]
+    pub fn parse_fen_with_all_fields() {
+        let game = Game::from_fen("r3k2r/8/8/8/4Pp2/8/8/R3K2R b Kq e3 3 42".to_string()).unwrap();
+
+        assert_eq!(Side::Black, game.current_player);
+        assert!(game.white.castle_kingside);
+        assert!(!game.white.castle_queenside);
+        assert!(!game.black.castle_kingside);
+        assert!(game.black.castle_queenside);
+        assert_eq!(Some(0x24), game.en_passant);
+        assert_eq!(3, game.halfmove_clock);
+        assert_eq!(42, game.fullmove_number);
+    }
+
+    #[test]
+    pub fn parse_fen_with_extra_whitespace() {
+        let fen = "r3k2r/8/8/8/4Pp2/8/8/R3K2R b Kq e3 3 42";
+        for raw in [
+            " r3k2r/8/8/8/4Pp2/8/8/R3K2R  b Kq\te3 3 42\n",
+            "r3k2r/8/8/8/4Pp2/8/8/R3K2R b\t\tKq e3 3   42\r\n",
+        ] {
+            assert_eq!(fen, test_game(raw).to_fen());
+        }
+
+        // Offsets still point into the string as given
+        assert_eq!(
+            Err(FenError::UnexpectedSide { offset: 29 }),
+            Game::from_fen("r3k2r/8/8/8/4Pp2/8/8/R3K2R \t x Kq e3 3 42".to_string())
+        );
+    }
+
+    #[test]
+    pub fn parse_fen_without_clocks() {
+        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - -".to_string()).unwrap();
+
+        assert_eq!(Side::Black, game.current_player);
+        assert!(!game.white.castle_kingside);
+        assert!(!game.black.castle_queenside);
+        assert_eq!(None, game.en_passant);
+        assert_eq!(0, game.halfmove_clock);
+        assert_eq!(1, game.fullmove_number);
+    }
+
+    #[test]
+    pub fn to_fen_matches_default() {
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            Game::new().to_fen()
+        );
+    }
+
+    #[test]
+    pub fn to_fen_after_moves() {
+        // Move white pawn to E4 & black knight to F6
+        let game = Game::new()
+            .make_move(Move::new(0x14, 0x34))
+            .unwrap()
+            .make_move(Move::new(0x76, 0x55))
+            .unwrap();
+        assert_eq!(
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2",
+            game.to_fen()
+        );
+
+        let game = Game::new().make_move(Move::new(0x14, 0x34)).unwrap();
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            game.to_fen()
+        );
+    }
+
+    #[test]
+    pub fn to_fen_round_trips() {
+        // The Chess960 position only comes back as Shredder-FEN, its rooks have to be named by file
+        for fen in TEST_POSITIONS {
+            let game = test_game(fen);
+            assert!(
+                fen == game.to_fen() || fen == game.to_shredder_fen(),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    pub fn chess960_castling_fields() {
+        // Shredder-FEN names every rook by its file, X-FEN only when K or Q would mean the outermost rook
+        let game = Game::from_fen(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9".to_string(),
+        )
+        .unwrap();
+        assert_eq!([7, 5], game.white.rook_files);
+        assert_eq!([7, 5], game.black.rook_files);
+        assert_eq!(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+            game.to_fen()
+        );
+        assert_eq!(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            game.to_shredder_fen()
+        );
+        assert_eq!(
+            game,
+            Game::from_fen(
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9".to_string()
+            )
+            .unwrap()
+        );
+
+        let game = Game::from_fen("rr2k3/8/8/8/8/8/8/1R2K1RR w Gb - 0 1".to_string()).unwrap();
+        assert_eq!([6, 0], game.white.rook_files);
+        assert_eq!([7, 1], game.black.rook_files);
+        assert_eq!("rr2k3/8/8/8/8/8/8/1R2K1RR w Gb - 0 1", game.to_fen());
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1",
+            Game::new().to_shredder_fen()
+        );
+    }
+
+    #[test]
+    pub fn parse_fen_rejects_bad_chess960_castling() {
+        // No rook on the named file, and a king off its home rank
+        assert_eq!(
+            Err(FenError::UnexpectedCastling {
+                offset: 23,
+                rune: 'G'
+            }),
+            Game::from_fen("4k3/8/8/8/8/8/8/4K2R w G - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedCastling {
+                offset: 23,
+                rune: 'H'
+            }),
+            Game::from_fen("8/8/8/8/8/8/4K3/4k2R w H - 0 1".to_string())
+        );
+    }
+
+    #[test]
+    pub fn parse_fen_with_check() {
+        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/r7 w - -".to_string()).unwrap();
+
+        assert!(game.white.check);
+        assert!(!game.black.check);
+    }
+
+    #[test]
+    pub fn parse_fen_with_black_check() {
+        let game = Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/7R b - -".to_string()).unwrap();
+
+        assert!(!game.white.check);
+        assert!(game.black.check);
+    }
+
+    #[test]
+    pub fn parse_fen_rejects_bad_placement() {
+        assert_eq!(
+            Err(FenError::UnexpectedPiece {
+                offset: 10,
+                rune: 'x'
+            }),
+            Game::from_fen("rnbqkbnr/pxpppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::RankOverflow { offset: 20 }),
+            Game::from_fen(
+                "rnbqkbnr/pppppppp/7p1/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string()
+            )
+        );
+        assert_eq!(
+            Err(FenError::RankOverflow { offset: 43 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::RankUnderflow { offset: 16 }),
+            Game::from_fen("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::RankUnderflow { offset: 42 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN w KQkq - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::WrongRankCount { ranks: 7 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::WrongRankCount { ranks: 9 }),
+            Game::from_fen(
+                "rnbqkbnr/pppppppp/8/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string()
+            )
+        );
+        assert_eq!(
+            Err(FenError::RankUnderflow { offset: 0 }),
+            Game::from_fen("".to_string())
+        );
+    }
+
+    #[test]
+    pub fn parse_fen_rejects_impossible_positions() {
+        // Each piece past the starting set takes the place of a pawn
+        assert_eq!(
+            Err(FenError::TooManyPieces { offset: 10 }),
+            Game::from_fen("QQQQQQQQ/QQQk4/8/8/8/8/8/4K3 w - -".to_string())
+        );
+        assert_eq!(
+            Err(FenError::TooManyPieces { offset: 11 }),
+            Game::from_fen("QQQQQ3/QQQQQ2P/7k/8/8/8/8/K7 w - - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::TooManyPieces { offset: 24 }),
+            Game::from_fen("4k3/8/8/8/8/8/PPPPPPPP/QQ2K3 w - -".to_string())
+        );
+        assert!(Game::from_fen("QQQQQQQQ/Q6k/8/8/8/8/8/K7 b - -".to_string()).is_ok());
+        assert!(Game::from_fen("RRRRRRRR/RR6/8/8/8/8/8/K5k1 b - -".to_string()).is_ok());
+        assert_eq!(
+            Err(FenError::TooManyPieces { offset: 21 }),
+            Game::from_fen("4k3/8/8/8/8/pppppppp/ppp5/4K3 w - -".to_string())
+        );
+        assert_eq!(
+            Err(FenError::PawnOnBackRank { offset: 0 }),
+            Game::from_fen("P3k3/8/8/8/8/8/8/4K3 w - -".to_string())
+        );
+        assert_eq!(
+            Err(FenError::PawnOnBackRank { offset: 16 }),
+            Game::from_fen("4k3/8/8/8/8/8/8/p3K3 b - -".to_string())
+        );
+        assert_eq!(
+            Err(FenError::OpponentInCheck(Side::Black)),
+            Game::from_fen("7k/8/8/8/8/8/8/4K2R w - - 0 1".to_string())
+        );
+    }
+
+    #[test]
+    pub fn parse_fen_rejects_bad_kings() {
+        assert_eq!(
+            Err(FenError::MissingKing(Side::Black)),
+            Game::from_fen("8/8/8/8/8/8/8/4K3 w - - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::ExtraKing(Side::White)),
+            Game::from_fen("4k3/8/8/8/8/8/8/3KK3 w - - 0 1".to_string())
+        );
+    }
+
+    #[test]
+    pub fn parse_fen_rejects_bad_fields() {
+        assert_eq!(
+            Err(FenError::UnexpectedSide { offset: 44 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedCastling {
+                offset: 49,
+                rune: 'x'
+            }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedCastling {
+                offset: 47,
+                rune: 'K'
+            }),
+            Game::from_fen("rnbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w QK - 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedEnPassant { offset: 51 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedEnPassant { offset: 51 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedHalfmoveClock { offset: 53 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedFullmoveNumber { offset: 55 }),
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 -1".to_string())
+        );
+        assert_eq!(
+            Err(FenError::UnexpectedField { offset: 57 }),
+            Game::from_fen(
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 x".to_string()
+            )
+        );
+    }
+}
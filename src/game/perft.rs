@@ -0,0 +1,231 @@
+use std::fmt::Display;
+
+use super::{Game, Move, Position};
+
+pub mod suite;
+
+// Count the positions at the end of every line of legal play depth moves long
+// The last level is counted from the number of moves rather than by playing them out
+pub(super) fn count<P: Position>(game: &mut P, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = game.generate_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    moves
+        .into_iter()
+        .map(|m| {
+            let undo = game.do_move(m);
+            let nodes = count(game, depth - 1);
+            game.undo_move(m, undo);
+            nodes
+        })
+        .sum()
+}
+
+// What the moves at one ply of a perft search did, the columns of the published perft tables
+// Captures include en passant, and double checks are counted as discovered checks too
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerftStats {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub discovered_checks: u64,
+    pub double_checks: u64,
+    pub checkmates: u64,
+}
+
+// Tally every move at every ply into the level for its ply, the first level being the moves from here
+pub(super) fn collect<P: Position>(levels: &mut [PerftStats], game: &mut P) {
+    let Some((level, deeper)) = levels.split_first_mut() else {
+        return;
+    };
+
+    for m in game.generate_moves() {
+        let undo = game.do_move(m);
+
+        level.nodes += 1;
+        level.captures += m.is_capture() as u64;
+        level.en_passants += m.is_en_passant() as u64;
+        level.castles += m.is_castle() as u64;
+        level.promotions += m.promotion().is_some() as u64;
+
+        let checkers = game.checkers();
+        if !checkers.is_empty() {
+            // The moved piece gives check from where it lands, as does the rook when castling
+            let rook = (m.to() & 0x70) | if m.to() & 0x07 == 6 { 5 } else { 3 };
+            let direct = |space: usize| space == m.to() || (m.is_castle() && space == rook);
+
+            level.checks += 1;
+            level.discovered_checks += checkers.iter().any(|space| !direct(*space)) as u64;
+            level.double_checks += (checkers.len() > 1) as u64;
+            level.checkmates += game.generate_moves().is_empty() as u64;
+        }
+
+        collect(deeper, game);
+        game.undo_move(m, undo);
+    }
+}
+
+// The perft count below each move from a position, for finding where two move generators disagree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divide {
+    moves: Vec<(String, u64)>,
+}
+
+impl Divide {
+    // Each move in coordinates with its count, sorted by the move
+    pub fn moves(&self) -> &[(String, u64)] {
+        &self.moves
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.moves.iter().map(|(_, nodes)| nodes).sum()
+    }
+}
+
+// One "e2e4: 13160" line per move, then the total, as other engines print it
+impl Display for Divide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (uci, nodes) in &self.moves {
+            writeln!(f, "{}: {}", uci, nodes)?;
+        }
+        write!(f, "\nNodes searched: {}", self.nodes())
+    }
+}
+
+impl Game {
+    // The number of leaf positions depth moves from here, see https://www.chessprogramming.org/Perft
+    pub fn perft(&self, depth: usize) -> u64 {
+        count(&mut self.clone(), depth)
+    }
+
+    // What the moves at each ply up to depth did, for comparing against the published perft tables
+    pub fn perft_stats(&self, depth: usize) -> Vec<PerftStats> {
+        let mut levels = vec![PerftStats::default(); depth];
+        collect(&mut levels, &mut self.clone());
+        levels
+    }
+
+    // Split the perft count by the first move, a depth of 0 has no moves to split by
+    pub fn divide(&self, depth: usize) -> Divide {
+        split(self, depth, |m| self.to_uci(m))
+    }
+}
+
+// Count below each move from a position, naming the moves with uci
+pub(super) fn split<P: Position>(game: &P, depth: usize, uci: impl Fn(Move) -> String) -> Divide {
+    let mut game = game.clone();
+    let mut moves: Vec<(String, u64)> = match depth {
+        0 => vec![],
+        _ => game
+            .generate_moves()
+            .into_iter()
+            .map(|m| {
+                let undo = game.do_move(m);
+                let nodes = count(&mut game, depth - 1);
+                game.undo_move(m, undo);
+                (uci(m), nodes)
+            })
+            .collect(),
+    };
+    moves.sort();
+
+    Divide { moves }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{bitboard::BitboardGame, test_game, TEST_POSITIONS};
+
+    // Play out every line depth moves long, checking a capture takes exactly one piece off the board and
+    // nothing else does
+    fn check_captures(game: &mut Game, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+
+        for m in game.generate_moves() {
+            let pieces = game.white.pieces.len() + game.black.pieces.len();
+            let undo = game.do_move(m);
+            let taken = pieces - (game.white.pieces.len() + game.black.pieces.len());
+            assert_eq!(
+                m.is_capture() as usize,
+                taken,
+                "{:?} in {}",
+                m,
+                game.to_fen()
+            );
+
+            check_captures(game, depth - 1);
+            game.undo_move(m, undo);
+        }
+    }
+
+    #[test]
+    pub fn perft_counts() {
+        let game = Game::new();
+        assert_eq!(
+            [1, 20, 400, 8902],
+            [0, 1, 2, 3].map(|depth| game.perft(depth))
+        );
+
+        let game = Game::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -".to_string(),
+        )
+        .unwrap();
+        assert_eq!(97862, game.perft(3));
+        assert_eq!(97862, BitboardGame::from(&game).perft(3));
+    }
+
+    #[test]
+    pub fn captures_take_one_piece() {
+        for fen in TEST_POSITIONS {
+            check_captures(&mut test_game(fen), 3);
+        }
+    }
+
+    #[test]
+    pub fn perft_stats_discovered_and_double_checks() {
+        // Every knight move uncovers the rook, and the two that reach the king check it twice
+        let game = Game::from_fen("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1".to_string()).unwrap();
+        let expected = PerftStats {
+            nodes: 19,
+            checks: 8,
+            discovered_checks: 8,
+            double_checks: 2,
+            ..PerftStats::default()
+        };
+
+        assert_eq!(vec![expected], game.perft_stats(1));
+        assert_eq!(vec![expected], BitboardGame::from(&game).perft_stats(1));
+    }
+
+    #[test]
+    pub fn divide_output() {
+        let divide = Game::new().divide(2);
+        assert_eq!(20, divide.moves().len());
+        assert_eq!(("a2a3".to_string(), 20), divide.moves()[0]);
+        assert_eq!(400, divide.nodes());
+
+        let output = divide.to_string();
+        assert!(output.starts_with("a2a3: 20\na2a4: 20\nb1a3: 20\n"));
+        assert!(output.ends_with("h2h4: 20\n\nNodes searched: 400"));
+
+        assert!(Game::new().divide(0).moves().is_empty());
+
+        let game = Game::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -".to_string(),
+        )
+        .unwrap();
+        assert_eq!(game.divide(2), BitboardGame::from(&game).divide(2));
+    }
+}
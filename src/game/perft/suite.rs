@@ -0,0 +1,297 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, BufRead},
+    time::{Duration, Instant},
+};
+
+use super::super::{
+    epd::{Epd, EpdError},
+    Game,
+};
+
+// Why a perft suite couldn't be read, lines are numbered from 1 at the start of the file
+#[derive(Debug)]
+pub enum SuiteError {
+    Io(io::Error),
+    Epd { line: usize, error: EpdError },
+}
+
+impl Display for SuiteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SuiteError::Io(error) => write!(f, "reading perft suite failed: {}", error),
+            SuiteError::Epd { line, error } => write!(f, "bad EPD on line {}: {}", line, error),
+        }
+    }
+}
+
+impl Error for SuiteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SuiteError::Io(error) => Some(error),
+            SuiteError::Epd { error, .. } => Some(error),
+        }
+    }
+}
+
+// How one position of a suite went, over the depths that were run before hitting the caps
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuiteResult {
+    line: usize,
+    epd: Epd,
+    depths: Vec<(usize, u64, u64)>, // The depth, the expected count and the count we got
+    elapsed: Duration,
+}
+
+impl SuiteResult {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn epd(&self) -> &Epd {
+        &self.epd
+    }
+
+    pub fn depths(&self) -> &[(usize, u64, u64)] {
+        &self.depths
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn passed(&self) -> bool {
+        self.depths
+            .iter()
+            .all(|(_, expected, nodes)| expected == nodes)
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.depths.iter().map(|(_, _, nodes)| nodes).sum()
+    }
+
+    pub fn nodes_per_second(&self) -> u64 {
+        match self.elapsed.as_nanos() {
+            0 => 0,
+            nanos => (self.nodes() as u128 * 1_000_000_000 / nanos) as u64,
+        }
+    }
+}
+
+// Like "ok   line 3: depth 5, 133987 nodes at 2512345 nps, 4k3/8/8/8/8/8/8/4K2R w K -", with each wrong
+// count after a failure
+impl Display for SuiteResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let depth = self.depths.last().map_or(0, |(depth, _, _)| *depth);
+        let fen = self.epd.game().to_fen();
+        let position = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+        write!(
+            f,
+            "{} line {}: depth {}, {} nodes at {} nps, {}",
+            if self.passed() { "ok  " } else { "FAIL" },
+            self.line,
+            depth,
+            self.nodes(),
+            self.nodes_per_second(),
+            self.epd.id().unwrap_or(&position)
+        )?;
+
+        for (depth, expected, nodes) in &self.depths {
+            if expected != nodes {
+                write!(
+                    f,
+                    "\n    D{} expected {} but counted {}",
+                    depth, expected, nodes
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Runs each position of a suite of EPD lines with D1 to D6 counts, like the standard perftsuite.epd
+// The bundled perftsuite.epd holds 56 of that suite's positions, not all of them
+// Depths go up from 1 until max_depth, or until the next depth looks like it would take the position past
+// time_limit, so one deep line can't stall the run
+pub struct PerftSuite<R> {
+    reader: R,
+    line_number: usize,
+    max_depth: usize,
+    time_limit: Duration,
+    perft: fn(&Game, usize) -> u64,
+}
+
+impl<R: BufRead> PerftSuite<R> {
+    pub fn new(reader: R, max_depth: usize, time_limit: Duration) -> Self {
+        PerftSuite {
+            reader,
+            line_number: 0,
+            max_depth,
+            time_limit,
+            perft: Game::perft,
+        }
+    }
+
+    // Count each position some other way than Game::perft, like with another board representation
+    pub fn counting_with(self, perft: fn(&Game, usize) -> u64) -> Self {
+        PerftSuite { perft, ..self }
+    }
+
+    fn run_line(&mut self) -> Result<Option<SuiteResult>, SuiteError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line).map_err(SuiteError::Io)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+
+        let epd = Epd::parse(&line).map_err(|error| SuiteError::Epd {
+            line: self.line_number,
+            error,
+        })?;
+
+        let start = Instant::now();
+        let mut depths = vec![];
+        let mut last = None; // How long the last depth took, its count and the count of the one before
+        for depth in 1..=self.max_depth {
+            let Some(expected) = epd.perft(depth) else {
+                continue;
+            };
+            // The first depth always runs, the rest only if they should finish in time
+            if let Some((took, nodes, previous)) = last {
+                if !next_depth_fits(start.elapsed(), took, nodes, previous, self.time_limit) {
+                    break;
+                }
+            }
+
+            let depth_start = Instant::now();
+            let nodes = (self.perft)(epd.game(), depth);
+            let previous = depths.last().map_or(1, |(_, _, nodes)| *nodes);
+            last = Some((depth_start.elapsed(), nodes, previous));
+            depths.push((depth, expected, nodes));
+        }
+
+        Ok(Some(SuiteResult {
+            line: self.line_number,
+            epd,
+            depths,
+            elapsed: start.elapsed(),
+        }))
+    }
+}
+
+// Whether another depth should finish within time_limit, guessing it takes as much longer than the last
+// one as the last one's count grew on the count before
+fn next_depth_fits(
+    elapsed: Duration,
+    took: Duration,
+    nodes: u64,
+    previous: u64,
+    time_limit: Duration,
+) -> bool {
+    let growth = nodes as f64 / previous.max(1) as f64;
+    elapsed + took.mul_f64(growth) < time_limit
+}
+
+impl<R: BufRead> Iterator for PerftSuite<R> {
+    type Item = Result<SuiteResult, SuiteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.run_line().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::bitboard::BitboardGame;
+    use std::{fs::File, io::BufReader};
+
+    #[test]
+    pub fn bundled_suite_passes() {
+        let file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/perftsuite.epd")).unwrap();
+        let results: Vec<SuiteResult> =
+            PerftSuite::new(BufReader::new(file), 3, Duration::from_secs(60))
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+        assert!(results.len() > 30);
+
+        // Both board representations get the same counts, so only the first few lines need the bitboard run
+        let file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/perftsuite.epd")).unwrap();
+        let bitboard: Vec<SuiteResult> =
+            PerftSuite::new(BufReader::new(file), 3, Duration::from_secs(60))
+                .counting_with(|game, depth| BitboardGame::from(game).perft(depth))
+                .take(10)
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert!(bitboard.iter().all(SuiteResult::passed));
+
+        for result in results {
+            assert!(result.passed(), "{}", result);
+            assert_eq!(3, result.depths().len());
+        }
+    }
+
+    #[test]
+    pub fn suite_reports_failures() {
+        let suite = "\n\
+            4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 66 ;D3 1197\n\
+            4k3/8/8/8/8/8/8/R3K3 w Q - ;D1 16 ;D2 70 ;D3 1287\n";
+        let results: Vec<SuiteResult> =
+            PerftSuite::new(suite.as_bytes(), 2, Duration::from_secs(60))
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+        assert_eq!(2, results.len());
+        assert!(results[0].passed());
+        assert_eq!((2, 81), (results[0].line(), results[0].nodes()));
+        assert!(!results[1].passed());
+        assert_eq!(&[(1, 16, 16), (2, 70, 71)], results[1].depths());
+        assert!(results[1]
+            .to_string()
+            .ends_with("4k3/8/8/8/8/8/8/R3K3 w Q -\n    D2 expected 70 but counted 71"));
+
+        // Stopping at the time limit still runs the first depth
+        let result = PerftSuite::new(suite.as_bytes(), 3, Duration::ZERO)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(&[(1, 15, 15)], result.depths());
+    }
+
+    #[test]
+    pub fn depths_stop_before_overrunning() {
+        let ms = Duration::from_millis;
+        let limit = Duration::from_secs(10);
+
+        // A depth of 1s that had 40 times the nodes of the one before needs about 40s for the next
+        assert!(!next_depth_fits(ms(1500), ms(1000), 4000, 100, limit));
+        assert!(next_depth_fits(ms(1500), ms(100), 4000, 100, limit));
+        assert!(!next_depth_fits(ms(9000), ms(100), 2000, 100, limit));
+        assert!(!next_depth_fits(ms(10000), ms(0), 20, 1, limit));
+        assert!(next_depth_fits(ms(0), ms(1), 20, 0, limit));
+    }
+
+    #[test]
+    pub fn suite_rejects_bad_lines() {
+        let suite = "4k3/8/8/8/8/8/8/4K2R w K - ;D1 15\n4k3/8/8/8/8/8/8/4K2R w K - ;D1 x\n";
+        let mut results = PerftSuite::new(suite.as_bytes(), 1, Duration::from_secs(60));
+
+        assert!(results.next().unwrap().is_ok());
+        assert!(matches!(
+            results.next(),
+            Some(Err(SuiteError::Epd {
+                line: 2,
+                error: EpdError::UnexpectedOperand { offset: 31 }
+            }))
+        ));
+        assert!(results.next().is_none());
+    }
+}
@@ -0,0 +1,791 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, BufRead, Write},
+};
+
+use super::{fen::FenError, san::SanError, Game, Move, Side};
+
+// Why a game in a PGN file couldn't be read, lines are numbered from 1 at the start of the file
+#[derive(Debug)]
+pub enum PgnError {
+    Io(io::Error),
+    UnexpectedTag {
+        line: usize,
+    },
+    Fen {
+        line: usize,
+        error: FenError,
+    },
+    UnexpectedMove {
+        line: usize,
+        san: String,
+        error: SanError,
+    },
+    UnexpectedToken {
+        line: usize,
+        token: String,
+    },
+    // A comment or variation still open at the end of the game
+    Unterminated {
+        line: usize,
+    },
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Io(error) => write!(f, "reading PGN failed: {}", error),
+            PgnError::UnexpectedTag { line } => write!(f, "malformed tag pair on line {}", line),
+            PgnError::Fen { line, error } => write!(f, "bad FEN tag on line {}: {}", line, error),
+            PgnError::UnexpectedMove { line, san, error } => {
+                write!(f, "can't play '{}' on line {}: {}", san, line, error)
+            }
+            PgnError::UnexpectedToken { line, token } => {
+                write!(f, "unexpected '{}' on line {}", token, line)
+            }
+            PgnError::Unterminated { line } => {
+                write!(f, "comment or variation from line {} is never closed", line)
+            }
+        }
+    }
+}
+
+impl Error for PgnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PgnError::Io(error) => Some(error),
+            PgnError::Fen { error, .. } => Some(error),
+            PgnError::UnexpectedMove { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// One game from a PGN file, with the moves of its main line
+// Variations, comments and NAGs are read past but not kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnGame {
+    tags: Vec<(String, String)>,
+    start: Game,
+    moves: Vec<Move>,
+    result: Option<String>,
+}
+
+impl PgnGame {
+    // The tag pairs in the order they were given
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The standard starting position, unless a FEN tag set up another
+    pub fn start(&self) -> &Game {
+        &self.start
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    // The result ending the movetext, like "1-0" or "*"
+    pub fn result(&self) -> Option<&str> {
+        self.result.as_deref()
+    }
+
+    // Every position of the main line, from the start to after the last move
+    pub fn positions(&self) -> impl Iterator<Item = Game> + '_ {
+        let mut game = self.start.clone();
+        std::iter::once(game.clone()).chain(self.moves.iter().map(move |m| {
+            game.do_move(*m);
+            game.clone()
+        }))
+    }
+
+    // The position after the last move, with the moves kept in its history
+    pub fn game(&self) -> Game {
+        let mut game = self.start.clone();
+        for m in &self.moves {
+            game.do_move(*m);
+        }
+
+        game
+    }
+}
+
+// Reads the games of a PGN file one at a time, so only the game being read is held in memory
+// A game that fails to parse gives an error, and reading carries on with the next one
+pub struct PgnReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    pending: bool, // The line read holds the start of the next game
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        PgnReader {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            pending: false,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        let read = self.reader.read_line(&mut self.line)?;
+        self.line_number += 1;
+        if self.line_number == 1 {
+            // Some editors start the file with a byte order mark
+            self.line = self.line.trim_start_matches('\u{feff}').to_string();
+        }
+
+        Ok(read > 0)
+    }
+
+    // Gather the tags and movetext of the next game, stopping at the tags of the one after it
+    fn read_game(&mut self) -> Result<Option<PgnGame>, PgnError> {
+        let mut tags = vec![];
+        let mut fen = None;
+        let mut movetext = String::new();
+        let mut movetext_line = None;
+        let mut in_comment = false;
+        let mut after_blank = false;
+        let mut bad_tag = None;
+
+        loop {
+            if !self.pending && !self.read_line().map_err(PgnError::Io)? {
+                break;
+            }
+            self.pending = false;
+            let line = self.line.trim();
+            let follows_blank = std::mem::replace(&mut after_blank, line.is_empty());
+
+            // Lines starting with % are escaped, for other programs to use
+            if line.starts_with('%') {
+                if movetext_line.is_some() {
+                    movetext.push('\n');
+                }
+                continue;
+            }
+
+            // A comment left open would swallow every game after it, so tag pairs after a blank line start
+            // the next game even inside one, and this game fails as unterminated
+            if line.starts_with('[') && in_comment && follows_blank && parse_tags(line).is_some() {
+                self.pending = true;
+                break;
+            }
+
+            if line.starts_with('[') && !in_comment {
+                if movetext_line.is_some() {
+                    self.pending = true;
+                    break;
+                }
+                // A bad tag fails the game, but only once the rest of it has been read past
+                match parse_tags(line) {
+                    Some(pairs) => {
+                        for (name, value) in pairs {
+                            if name == "FEN" {
+                                fen = Some((self.line_number, value.clone()));
+                            }
+                            tags.push((name, value));
+                        }
+                    }
+                    None => {
+                        bad_tag.get_or_insert(self.line_number);
+                    }
+                }
+                continue;
+            }
+
+            if movetext_line.is_none() {
+                if line.is_empty() {
+                    continue;
+                }
+                movetext_line = Some(self.line_number);
+            }
+
+            // Follow brace comments across lines, so a comment line that looks like a tag isn't taken for one
+            for rune in line.chars() {
+                match rune {
+                    '}' if in_comment => in_comment = false,
+                    '{' if !in_comment => in_comment = true,
+                    ';' if !in_comment => break,
+                    _ => {}
+                }
+            }
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+
+        if let Some(line) = bad_tag {
+            return Err(PgnError::UnexpectedTag { line });
+        }
+        if tags.is_empty() && movetext_line.is_none() {
+            return Ok(None);
+        }
+
+        let start = match fen {
+            Some((line, fen)) => {
+                Game::from_fen(fen).map_err(|error| PgnError::Fen { line, error })?
+            }
+            None => Game::new(),
+        };
+        let (moves, result) = parse_movetext(
+            start.clone(),
+            &movetext,
+            movetext_line.unwrap_or(self.line_number),
+        )?;
+
+        Ok(Some(PgnGame {
+            tags,
+            start,
+            moves,
+            result,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_game().transpose()
+    }
+}
+
+// A move to write out, with an optional comment after it, like an evaluation, and the lines that could
+// have been played instead of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnMove {
+    m: Move,
+    comment: Option<String>,
+    variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(m: Move) -> Self {
+        PgnMove {
+            m,
+            comment: None,
+            variations: vec![],
+        }
+    }
+
+    pub fn with_comment(self, comment: &str) -> Self {
+        PgnMove {
+            comment: Some(comment.to_string()),
+            ..self
+        }
+    }
+
+    // A variation starts from the position before this move
+    pub fn with_variation(mut self, variation: Vec<PgnMove>) -> Self {
+        self.variations.push(variation);
+        self
+    }
+}
+
+impl From<Move> for PgnMove {
+    fn from(m: Move) -> Self {
+        PgnMove::new(m)
+    }
+}
+
+// The tags every exported game starts with, in order, and what they default to when missing
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+// No line of movetext is longer than this
+const LINE_LENGTH: usize = 80;
+
+// Writes games out in PGN's export format, which any standard viewer can open
+pub struct PgnWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> PgnWriter<W> {
+    pub fn new(writer: W) -> Self {
+        PgnWriter { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    // Write one game, whose moves must all be legal, followed by a blank line to separate it from the next
+    // The Seven Tag Roster comes first with the result given here, then the other tags in their order,
+    // and games not from the standard start get SetUp and FEN tags
+    pub fn write_game(
+        &mut self,
+        tags: &[(String, String)],
+        start: &Game,
+        moves: &[PgnMove],
+        result: &str,
+    ) -> io::Result<()> {
+        let value = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| tag == name)
+                .map(|(_, value)| value.as_str())
+        };
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => result,
+                _ => value(name).unwrap_or(default),
+            };
+            self.write_tag(name, value)?;
+        }
+        // Compared by FEN, as the game's history doesn't change where it starts
+        if start.to_fen() != Game::new().to_fen() {
+            self.write_tag("SetUp", "1")?;
+            self.write_tag("FEN", &start.to_fen())?;
+        }
+        for (name, value) in tags {
+            let skip = SEVEN_TAG_ROSTER.iter().any(|(roster, _)| roster == name)
+                || name == "SetUp"
+                || name == "FEN";
+            if !skip {
+                self.write_tag(name, value)?;
+            }
+        }
+        writeln!(self.writer)?;
+
+        let mut tokens = vec![];
+        movetext_tokens(&mut tokens, start, moves);
+        tokens.push(result.to_string());
+
+        // Fill each line with as many tokens as fit
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() {
+                if line.chars().count() + 1 + token.chars().count() > LINE_LENGTH {
+                    writeln!(self.writer, "{}", line)?;
+                    line.clear();
+                } else {
+                    line.push(' ');
+                }
+            }
+            line.push_str(&token);
+        }
+        writeln!(self.writer, "{}", line)?;
+        writeln!(self.writer)
+    }
+
+    fn write_tag(&mut self, name: &str, value: &str) -> io::Result<()> {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(self.writer, "[{} \"{}\"]", name, value)
+    }
+}
+
+// Break the moves into the words of the movetext, with move numbers, comments and variations
+// Black's moves are numbered as "12..." when they start the line or follow a comment or variation
+fn movetext_tokens(tokens: &mut Vec<String>, start: &Game, moves: &[PgnMove]) {
+    let mut game = start.clone();
+    let mut numbered = false;
+
+    for pgn_move in moves {
+        match game.current_player {
+            Side::White => tokens.push(format!("{}.", game.fullmove_number)),
+            Side::Black if !numbered => tokens.push(format!("{}...", game.fullmove_number)),
+            Side::Black => {}
+        }
+        tokens.push(game.to_san(pgn_move.m));
+        numbered = true;
+
+        // Nothing escapes a brace in a comment, so any closing one is dropped rather than end it early
+        if let Some(comment) = &pgn_move.comment {
+            let comment = format!("{{{}}}", comment.replace('}', "").trim());
+            tokens.extend(comment.split_whitespace().map(str::to_string));
+            numbered = false;
+        }
+
+        // Variations replace this move, so they are played from the position before it
+        for variation in &pgn_move.variations {
+            let mut variation_tokens = vec![];
+            movetext_tokens(&mut variation_tokens, &game, variation);
+            if let Some(last) = variation_tokens.len().checked_sub(1) {
+                variation_tokens[0].insert(0, '(');
+                variation_tokens[last].push(')');
+                tokens.append(&mut variation_tokens);
+                numbered = false;
+            }
+        }
+
+        game.do_move(pgn_move.m);
+    }
+}
+
+// Parse every tag pair on a line, like [Event "F/S Return Match"], with \" and \\ escaped in the value
+fn parse_tags(line: &str) -> Option<Vec<(String, String)>> {
+    let mut tags = vec![];
+    let mut runes = line.chars().peekable();
+
+    loop {
+        while runes.next_if(|rune| rune.is_whitespace()).is_some() {}
+        match runes.next() {
+            Some('[') => {}
+            None => return Some(tags),
+            _ => return None,
+        }
+
+        while runes.next_if(|rune| rune.is_whitespace()).is_some() {}
+        let mut name = String::new();
+        while let Some(rune) = runes.next_if(|rune| rune.is_alphanumeric() || *rune == '_') {
+            name.push(rune);
+        }
+        while runes.next_if(|rune| rune.is_whitespace()).is_some() {}
+        if name.is_empty() || runes.next() != Some('"') {
+            return None;
+        }
+
+        let mut value = String::new();
+        loop {
+            match runes.next()? {
+                '"' => break,
+                '\\' => value.push(runes.next()?),
+                rune => value.push(rune),
+            }
+        }
+
+        while runes.next_if(|rune| rune.is_whitespace()).is_some() {}
+        if runes.next() != Some(']') {
+            return None;
+        }
+        tags.push((name, value));
+    }
+}
+
+// Play the main line of the movetext from the start, skipping comments, NAGs, move numbers and variations
+fn parse_movetext(
+    mut game: Game,
+    movetext: &str,
+    first_line: usize,
+) -> Result<(Vec<Move>, Option<String>), PgnError> {
+    let mut moves = vec![];
+    let mut result = None;
+    let mut depth = 0;
+    let mut variation_line = first_line;
+    let mut line = first_line;
+    let mut runes = movetext.chars().peekable();
+
+    while let Some(rune) = runes.next() {
+        match rune {
+            '\n' => line += 1,
+            '{' => {
+                let start = line;
+                loop {
+                    match runes.next() {
+                        Some('}') => break,
+                        Some('\n') => line += 1,
+                        Some(_) => {}
+                        None => return Err(PgnError::Unterminated { line: start }),
+                    }
+                }
+            }
+            ';' => while runes.next_if(|rune| *rune != '\n').is_some() {},
+            '(' => {
+                if depth == 0 {
+                    variation_line = line;
+                }
+                depth += 1;
+            }
+            ')' if depth > 0 => depth -= 1,
+            ')' => {
+                return Err(PgnError::UnexpectedToken {
+                    line,
+                    token: ")".to_string(),
+                })
+            }
+            _ if rune.is_whitespace() => {}
+            _ => {
+                let mut token = rune.to_string();
+                while let Some(rune) =
+                    runes.next_if(|rune| !rune.is_whitespace() && !"{}();".contains(*rune))
+                {
+                    token.push(rune);
+                }
+
+                // Moves in variations aren't played, and NAGs like $1 only annotate
+                if depth > 0 || token.starts_with('$') {
+                    continue;
+                }
+                if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) {
+                    result = Some(token);
+                    continue;
+                }
+
+                // Move numbers like "12." or "12..." can be written against the move
+                let san = match token.rfind('.') {
+                    Some(dot) => &token[dot + 1..],
+                    None => &token,
+                };
+                if san.trim_matches(['!', '?']).is_empty() {
+                    continue;
+                }
+                let m = game
+                    .parse_san(san)
+                    .map_err(|error| PgnError::UnexpectedMove {
+                        line,
+                        san: san.to_string(),
+                        error,
+                    })?;
+                game.do_move(m);
+                moves.push(m);
+            }
+        }
+    }
+    if depth > 0 {
+        return Err(PgnError::Unterminated {
+            line: variation_line,
+        });
+    }
+
+    Ok((moves, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Side, Status};
+
+    const GAMES: &str = r#"[Event "Casual"]
+[White "A"] [Black "B \"the second\""]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {A comment
+[that looks like a tag] across lines} Nc6 $1 3. Bb5 (3. Bc4 Bc5 (3... Nf6)
+4. c3) 3... a6!? ; the rest of the line (is ignored
+4.Ba4 Nf6 5. O-O 1-0
+
+[Event "Set up"]
+[SetUp "1"]
+[FEN "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"]
+
+% An escaped line
+1. Ra8# 1-0
+"#;
+
+    #[test]
+    pub fn read_games() {
+        let games: Vec<PgnGame> = PgnReader::new(GAMES.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(2, games.len());
+
+        let game = &games[0];
+        assert_eq!(4, game.tags().len());
+        assert_eq!(Some("B \"the second\""), game.tag("Black"));
+        assert_eq!(None, game.tag("Site"));
+        assert_eq!(&Game::new(), game.start());
+        assert_eq!(9, game.moves().len());
+        assert_eq!(Some("1-0"), game.result());
+        assert_eq!(
+            "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 5",
+            game.game().to_fen()
+        );
+
+        let game = &games[1];
+        assert_eq!(Some("1"), game.tag("SetUp"));
+        assert_eq!(1, game.moves().len());
+        assert_eq!(Status::Checkmate(Side::White), game.game().status());
+    }
+
+    #[test]
+    pub fn positions_follow_the_main_line() {
+        let game = PgnReader::new(GAMES.as_bytes()).next().unwrap().unwrap();
+        let positions: Vec<Game> = game.positions().collect();
+
+        assert_eq!(10, positions.len());
+        assert_eq!(&Game::new(), &positions[0]);
+        assert_eq!(game.game(), positions[9]);
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            positions[1].to_fen()
+        );
+    }
+
+    #[test]
+    pub fn read_games_after_errors() {
+        let pgn = "[Event \"Broken\"]\n\n1. e4 e5\n2. Ke3 1-0\n\n[Event \"Fine\"]\n\n1. d4 *\n\n\
+                   [Event \"Unclosed\"]\n\n1. d4 (1. e4\n\n[Event Bad]\n\n1. c4 *\n";
+        let mut games = PgnReader::new(pgn.as_bytes());
+
+        match games.next() {
+            Some(Err(PgnError::UnexpectedMove { line, san, error })) => {
+                assert_eq!(
+                    (4, "Ke3", SanError::IllegalMove),
+                    (line, san.as_str(), error)
+                )
+            }
+            other => panic!("expected an illegal move, got {:?}", other),
+        }
+        assert_eq!(Some("*"), games.next().unwrap().unwrap().result());
+        assert!(matches!(
+            games.next(),
+            Some(Err(PgnError::Unterminated { line: 12 }))
+        ));
+        assert!(matches!(
+            games.next(),
+            Some(Err(PgnError::UnexpectedTag { line: 14 }))
+        ));
+        assert!(games.next().is_none());
+
+        // Only a tag pair after a blank line ends an unclosed comment, not one inside the comment's text
+        let pgn = "[Event \"Open\"]\n\n1. e4 {never closed\n[Note \"quoted\"]\n1-0\n\n\
+                   [Event \"Next\"]\n\n1. d4 d5 *\n";
+        let mut games = PgnReader::new(pgn.as_bytes());
+        assert!(matches!(
+            games.next(),
+            Some(Err(PgnError::Unterminated { line: 3 }))
+        ));
+        let game = games.next().unwrap().unwrap();
+        assert_eq!(Some("Next"), game.tag("Event"));
+        assert_eq!(2, game.moves().len());
+        assert!(games.next().is_none());
+    }
+
+    // Play SAN moves from a position, giving each as a move to write
+    fn line(start: &Game, sans: &[&str]) -> Vec<PgnMove> {
+        let mut game = start.clone();
+        sans.iter()
+            .map(|san| {
+                let m = game.parse_san(san).unwrap();
+                game.do_move(m);
+                PgnMove::from(m)
+            })
+            .collect()
+    }
+
+    fn write(tags: &[(String, String)], start: &Game, moves: &[PgnMove], result: &str) -> String {
+        let mut writer = PgnWriter::new(vec![]);
+        writer.write_game(tags, start, moves, result).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    pub fn write_comments_and_variations() {
+        let start = Game::new();
+        let mut moves = line(&start, &["e4", "e5", "Nf3"]);
+        let after_d4 = start.make_move(start.parse_san("d4").unwrap()).unwrap();
+        let mut variation = line(&start, &["d4", "d5"]);
+        variation[1] = variation[1]
+            .clone()
+            .with_variation(line(&after_d4, &["Nf6", "c4"]));
+        moves[0] = moves[0]
+            .clone()
+            .with_comment("+0.30")
+            .with_variation(variation);
+
+        assert_eq!(
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"?\"]\n\
+             [Black \"?\"]\n[Result \"*\"]\n\n\
+             1. e4 {+0.30} (1. d4 d5 (1... Nf6 2. c4)) 1... e5 2. Nf3 *\n\n",
+            write(&[], &start, &moves, "*")
+        );
+    }
+
+    #[test]
+    pub fn write_tags_and_set_up_positions() {
+        let start = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 30".to_string()).unwrap();
+        let tags = [
+            ("Annotator", "Barnacle"),
+            ("White", "Engine \"A\""),
+            ("Result", "0-1"),
+            ("FEN", "8/8/8/8/8/8/8/8 w - - 0 1"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let moves = line(&start, &["h6", "Ra8+", "Kh7"]);
+
+        assert_eq!(
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"Engine \\\"A\\\"\"]\n[Black \"?\"]\n[Result \"1/2-1/2\"]\n[SetUp \"1\"]\n\
+             [FEN \"6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 30\"]\n[Annotator \"Barnacle\"]\n\n\
+             30... h6 31. Ra8+ Kh7 1/2-1/2\n\n",
+            write(&tags, &start, &moves, "1/2-1/2")
+        );
+    }
+
+    #[test]
+    pub fn standard_start_needs_no_fen() {
+        // The standard position with a history of having been seen before
+        let mut start = Game::new();
+        start.history.push(start.key);
+        assert_ne!(Game::new(), start);
+
+        let moves = line(&start, &["e4"]);
+        assert_eq!(
+            write(&[], &Game::new(), &moves, "*"),
+            write(&[], &start, &moves, "*")
+        );
+    }
+
+    #[test]
+    pub fn written_games_wrap_and_read_back() {
+        // Play whichever move comes first until the game ends, with an evaluation after every move
+        let mut game = Game::new();
+        let mut moves = vec![];
+        while moves.len() < 120 && game.status() == Status::Ongoing {
+            let m = game.generate_moves()[0];
+            moves.push(PgnMove::new(m).with_comment(&format!("{} plies", moves.len())));
+            game.do_move(m);
+        }
+
+        let tags = [("Event".to_string(), "Long".to_string())];
+        let pgn = write(&tags, &Game::new(), &moves, "*");
+        assert!(pgn.lines().all(|line| line.len() <= 80));
+        assert!(pgn.lines().any(|line| line.len() > 70));
+
+        let read = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(Some("Long"), read.tag("Event"));
+        assert_eq!(Some("*"), read.result());
+        assert_eq!(
+            moves.iter().map(|m| m.m).collect::<Vec<Move>>(),
+            read.moves()
+        );
+    }
+
+    #[test]
+    pub fn written_comments_stay_closed() {
+        let start = Game::new();
+        let mut moves = line(&start, &["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"]);
+        moves[4] = moves[4].clone().with_comment("threatens {Qxf7#} 1-0 }");
+
+        let pgn = write(&[], &start, &moves, "1-0");
+        assert!(pgn.contains("3. Bc4 {threatens {Qxf7# 1-0} 3... Nf6"));
+
+        let read = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(Some("1-0"), read.result());
+        assert_eq!(
+            moves.iter().map(|m| m.m).collect::<Vec<Move>>(),
+            read.moves()
+        );
+    }
+
+    #[test]
+    pub fn parse_tag_pairs() {
+        assert_eq!(
+            Some(vec![
+                ("Event".to_string(), "F/S Return Match".to_string()),
+                ("Site".to_string(), "a\\b".to_string()),
+            ]),
+            parse_tags(r#"[Event "F/S Return Match"]  [ Site "a\\b" ]"#)
+        );
+        assert_eq!(None, parse_tags(r#"[Event "Unclosed]"#));
+        assert_eq!(None, parse_tags(r#"[Event Unquoted]"#));
+        assert_eq!(None, parse_tags(r#"[ "No name"]"#));
+    }
+}
@@ -0,0 +1,264 @@
+use std::{error::Error, fmt::Display};
+
+use super::{parse_space, space_name, Game, Move, Piece};
+
+// Why a SAN string couldn't be turned into a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SanError {
+    UnexpectedSyntax,
+    IllegalMove,
+    AmbiguousMove,
+}
+
+impl Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::UnexpectedSyntax => write!(f, "not a move in standard algebraic notation"),
+            SanError::IllegalMove => write!(f, "no legal move matches"),
+            SanError::AmbiguousMove => write!(f, "more than one legal move matches"),
+        }
+    }
+}
+
+impl Error for SanError {}
+
+fn piece_letter(piece: Piece) -> Option<char> {
+    match piece {
+        Piece::King => Some('K'),
+        Piece::Queen => Some('Q'),
+        Piece::Rook => Some('R'),
+        Piece::Knight => Some('N'),
+        Piece::Bishop => Some('B'),
+        Piece::Pawn => None,
+    }
+}
+
+fn letter_piece(letter: char) -> Option<Piece> {
+    match letter {
+        'K' => Some(Piece::King),
+        'Q' => Some(Piece::Queen),
+        'R' => Some(Piece::Rook),
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        _ => None,
+    }
+}
+
+impl Game {
+    // Write out a legal move in Standard Algebraic Notation, like "Nbd7", "exd8=N+" or "O-O#"
+    pub fn to_san(&self, m: Move) -> String {
+        let mut san = String::new();
+        let (src, dest) = (m.from(), m.to());
+        let piece = self.board[src].unwrap().piece;
+
+        if m.is_castle() {
+            san.push_str(if dest & 0x07 == 6 { "O-O" } else { "O-O-O" });
+        } else {
+            match piece_letter(piece) {
+                Some(letter) => {
+                    san.push(letter);
+
+                    // Name the file the piece comes from if that tells it apart from the others of its kind
+                    // that could move here, otherwise the rank, and both if neither is enough
+                    let others: Vec<usize> = self
+                        .generate_moves()
+                        .iter()
+                        .filter(|other| {
+                            other.to() == dest
+                                && other.from() != src
+                                && self.board[other.from()].unwrap().piece == piece
+                        })
+                        .map(|other| other.from())
+                        .collect();
+                    let name = space_name(src);
+                    if !others.is_empty() {
+                        if others.iter().all(|other| other & 0x07 != src & 0x07) {
+                            san.push_str(&name[..1]);
+                        } else if others.iter().all(|other| other >> 4 != src >> 4) {
+                            san.push_str(&name[1..]);
+                        } else {
+                            san.push_str(&name);
+                        }
+                    }
+                }
+                // Pawns only give their file, and only when capturing
+                None if m.is_capture() => san.push_str(&space_name(src)[..1]),
+                None => {}
+            }
+            if m.is_capture() {
+                san.push('x');
+            }
+            san.push_str(&space_name(dest));
+            if let Some(letter) = m.promotion().and_then(piece_letter) {
+                san.push('=');
+                san.push(letter);
+            }
+        }
+
+        let after = self.make_move(m).unwrap();
+        if after.get_player().check {
+            san.push(if after.generate_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+
+        san
+    }
+
+    // Find the one legal move a Standard Algebraic Notation string describes
+    // Check, capture and annotation marks are accepted but not required to match
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        let moves = self.generate_moves();
+
+        let matches: Vec<Move> = match san {
+            "O-O" | "0-0" => moves
+                .into_iter()
+                .filter(|m| m.is_castle() && m.to() & 0x07 == 6)
+                .collect(),
+            "O-O-O" | "0-0-0" => moves
+                .into_iter()
+                .filter(|m| m.is_castle() && m.to() & 0x07 == 2)
+                .collect(),
+            _ => {
+                let mut runes: Vec<char> = san.chars().filter(|rune| *rune != 'x').collect();
+
+                // A piece letter up front, or a pawn when there isn't one
+                let piece = match runes.first().copied().and_then(letter_piece) {
+                    Some(piece) => {
+                        runes.remove(0);
+                        piece
+                    }
+                    None => Piece::Pawn,
+                };
+
+                // A promotion at the end, with or without the '='
+                let promotion = match runes.last().copied().and_then(letter_piece) {
+                    Some(promotion) if piece == Piece::Pawn => {
+                        runes.pop();
+                        if runes.last() == Some(&'=') {
+                            runes.pop();
+                        }
+                        Some(promotion)
+                    }
+                    _ => None,
+                };
+
+                // The destination, with whatever is left before it telling apart the pieces that could move there
+                if runes.len() < 2 {
+                    return Err(SanError::UnexpectedSyntax);
+                }
+                let dest: String = runes.split_off(runes.len() - 2).into_iter().collect();
+                let dest = parse_space(&dest).ok_or(SanError::UnexpectedSyntax)?;
+                let (mut file, mut rank) = (None, None);
+                for rune in runes {
+                    match rune {
+                        'a'..='h' if file.is_none() => file = Some(rune as usize - 'a' as usize),
+                        '1'..='8' if rank.is_none() => rank = Some(rune as usize - '1' as usize),
+                        _ => return Err(SanError::UnexpectedSyntax),
+                    }
+                }
+
+                moves
+                    .into_iter()
+                    .filter(|m| {
+                        !m.is_castle()
+                            && m.to() == dest
+                            && m.promotion() == promotion
+                            && self.board[m.from()].unwrap().piece == piece
+                            && file.is_none_or(|file| m.from() & 0x07 == file)
+                            && rank.is_none_or(|rank| m.from() >> 4 == rank)
+                    })
+                    .collect()
+            }
+        };
+
+        match matches[..] {
+            [m] => Ok(m),
+            [] => Err(SanError::IllegalMove),
+            _ => Err(SanError::AmbiguousMove),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{assert_moves_round_trip, test_game};
+
+    #[test]
+    pub fn san_round_trips() {
+        assert_moves_round_trip(Game::to_san, Game::parse_san);
+    }
+
+    #[test]
+    pub fn san_from_start() {
+        let game = Game::new();
+
+        assert_eq!(
+            Ok((0x14, 0x34)),
+            game.parse_san("e4").map(|m| (m.from(), m.to()))
+        );
+        assert_eq!("e4", game.to_san(game.parse_san("e4").unwrap()));
+        assert_eq!("Nf3", game.to_san(game.parse_san("Nf3").unwrap()));
+        assert_eq!(Ok(0x25), game.parse_san("Ngf3").map(|m| m.to()));
+    }
+
+    #[test]
+    pub fn san_disambiguation() {
+        let game = test_game("4k3/8/8/7R/8/Q7/8/QNQ1KN1R w - - 0 1");
+        let san = |src: usize, dest: usize| {
+            let m = game
+                .generate_moves()
+                .into_iter()
+                .find(|m| m.from() == src && m.to() == dest)
+                .unwrap();
+            game.to_san(m)
+        };
+
+        // By file, by rank, and by both when neither is enough on its own
+        assert_eq!("Nbd2", san(0x01, 0x13));
+        assert_eq!("Nfd2", san(0x05, 0x13));
+        assert_eq!("R1h3", san(0x07, 0x27));
+        assert_eq!("R5h3", san(0x47, 0x27));
+        assert_eq!("Qa1b2", san(0x00, 0x11));
+        assert_eq!("Q3b2", san(0x20, 0x11));
+        assert_eq!("Qcb2", san(0x02, 0x11));
+        assert_eq!(Err(SanError::AmbiguousMove), game.parse_san("Nd2"));
+        assert_eq!(Ok(0x05), game.parse_san("Nfd2").map(|m| m.from()));
+    }
+
+    #[test]
+    pub fn san_captures_promotions_and_checks() {
+        let game = test_game("3r4/4Pk2/8/8/8/8/8/K7 w - - 0 1");
+        let m = game.parse_san("exd8=N+").unwrap();
+        assert_eq!(
+            (0x64, 0x73, Some(Piece::Knight)),
+            (m.from(), m.to(), m.promotion())
+        );
+        assert_eq!("exd8=N+", game.to_san(m));
+        assert_eq!(Ok(m), game.parse_san("exd8N"));
+        assert_eq!("e8=Q+", game.to_san(game.parse_san("e8=Q").unwrap()));
+
+        let game = test_game("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!("Ra8#", game.to_san(game.parse_san("Ra8").unwrap()));
+
+        let game = test_game("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -");
+        assert_eq!("O-O", game.to_san(game.parse_san("O-O").unwrap()));
+        assert_eq!("O-O-O", game.to_san(game.parse_san("0-0-0").unwrap()));
+        assert_eq!("Qxf6", game.to_san(game.parse_san("Qf6").unwrap()));
+    }
+
+    #[test]
+    pub fn parse_san_rejects_bad_moves() {
+        let game = Game::new();
+
+        assert_eq!(Err(SanError::IllegalMove), game.parse_san("Nf6"));
+        assert_eq!(Err(SanError::IllegalMove), game.parse_san("O-O"));
+        assert_eq!(Err(SanError::UnexpectedSyntax), game.parse_san("e"));
+        assert_eq!(Err(SanError::UnexpectedSyntax), game.parse_san("Nz3"));
+        assert_eq!(Err(SanError::UnexpectedSyntax), game.parse_san("Nbbd2"));
+    }
+}
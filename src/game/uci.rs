@@ -0,0 +1,186 @@
+use std::{error::Error, fmt::Display};
+
+use super::{parse_space, space_name, Game, Move, Piece};
+
+// Why a move in UCI's coordinate notation was rejected, holding the move as it was given
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UciError {
+    UnexpectedSyntax(String),
+    IllegalMove(String),
+}
+
+impl Display for UciError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UciError::UnexpectedSyntax(uci) => write!(f, "'{}' is not a coordinate move", uci),
+            UciError::IllegalMove(uci) => write!(f, "'{}' is not a legal move", uci),
+        }
+    }
+}
+
+impl Error for UciError {}
+
+impl Game {
+    // Write out a legal move in coordinates, like "e2e4" or "e7e8q"
+    // Castling is written as the king moving two spaces, except in Chess960 positions where that would be
+    // wrong or could be a plain king move, which are written as the king taking its own rook as GUIs expect
+    pub fn to_uci(&self, m: Move) -> String {
+        let (src, mut dest) = (m.from(), m.to());
+        if m.is_castle() {
+            let (rook, _) = self.castling_rook(self.current_player, dest);
+            if src & 0x07 != 4 || (rook & 0x07 != 0 && rook & 0x07 != 7) {
+                dest = rook;
+            }
+        }
+
+        let mut uci = space_name(src) + &space_name(dest);
+        if let Some(promotion) = m.promotion() {
+            uci.push(match promotion {
+                Piece::Queen => 'q',
+                Piece::Rook => 'r',
+                Piece::Bishop => 'b',
+                _ => 'n',
+            });
+        }
+
+        uci
+    }
+
+    // Find the legal move a coordinate string describes, in either of the forms castling can be written in
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, UciError> {
+        let syntax = || UciError::UnexpectedSyntax(uci.to_string());
+        let (src, dest, promotion) = match (uci.get(..2), uci.get(2..4), uci.get(4..)) {
+            (Some(src), Some(dest), Some(promotion)) => (
+                parse_space(src).ok_or_else(syntax)?,
+                parse_space(dest).ok_or_else(syntax)?,
+                match promotion {
+                    "" => None,
+                    "q" => Some(Piece::Queen),
+                    "r" => Some(Piece::Rook),
+                    "b" => Some(Piece::Bishop),
+                    "n" => Some(Piece::Knight),
+                    _ => return Err(syntax()),
+                },
+            ),
+            _ => return Err(syntax()),
+        };
+
+        self.generate_moves()
+            .into_iter()
+            .find(|m| {
+                m.from() == src
+                    && m.promotion() == promotion
+                    && if m.is_castle() {
+                        self.to_uci(*m) == uci
+                            || self.castling_rook(self.current_player, m.to()).0 == dest
+                    } else {
+                        m.to() == dest
+                    }
+            })
+            .ok_or_else(|| UciError::IllegalMove(uci.to_string()))
+    }
+
+    // Play a list of coordinate moves from a starting position, as UCI's position command gives them
+    // The moves are kept in the history, so repetitions through them count
+    pub fn from_moves<S: AsRef<str>>(
+        start: Game,
+        moves: impl IntoIterator<Item = S>,
+    ) -> Result<Game, UciError> {
+        let mut game = start;
+        for uci in moves {
+            let m = game.parse_uci(uci.as_ref())?;
+            game.do_move(m);
+        }
+
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{assert_moves_round_trip, test_game, Status};
+
+    #[test]
+    pub fn uci_round_trips() {
+        assert_moves_round_trip(Game::to_uci, Game::parse_uci);
+    }
+
+    #[test]
+    pub fn uci_moves() {
+        let game = Game::new();
+        let m = game.parse_uci("e2e4").unwrap();
+        assert_eq!((0x14, 0x34), (m.from(), m.to()));
+        assert_eq!("e2e4", game.to_uci(m));
+
+        let game = test_game("3r4/4Pk2/8/8/8/8/8/K7 w - - 0 1");
+        let m = game.parse_uci("e7d8n").unwrap();
+        assert_eq!(
+            (0x64, 0x73, Some(Piece::Knight)),
+            (m.from(), m.to(), m.promotion())
+        );
+        assert_eq!("e7e8q", game.to_uci(game.parse_uci("e7e8q").unwrap()));
+    }
+
+    #[test]
+    pub fn uci_castling() {
+        // Standard castling is the king moving two spaces, and taking the rook is accepted too
+        let game = test_game("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -");
+        let m = game.parse_uci("e1g1").unwrap();
+        assert!(m.is_castle());
+        assert_eq!("e1g1", game.to_uci(m));
+        assert_eq!(Ok(m), game.parse_uci("e1h1"));
+
+        // Here the king taking the rook is castling, and the king moving to c1 is just a king move
+        let game = test_game("4k3/8/8/8/8/8/8/RK6 w Q - 0 1");
+        let m = game.parse_uci("b1a1").unwrap();
+        assert!(m.is_castle());
+        assert_eq!(0x02, m.to());
+        assert_eq!("b1a1", game.to_uci(m));
+        assert!(!game.parse_uci("b1c1").unwrap().is_castle());
+    }
+
+    #[test]
+    pub fn parse_uci_rejects_bad_moves() {
+        let game = Game::new();
+        assert_eq!(
+            Err(UciError::IllegalMove("e2e5".to_string())),
+            game.parse_uci("e2e5")
+        );
+        assert_eq!(
+            Err(UciError::IllegalMove("e2e4q".to_string())),
+            game.parse_uci("e2e4q")
+        );
+        for uci in ["", "e2", "e2e", "e2e9", "e2e4x", "e2e4qq", "é2e4"] {
+            assert_eq!(
+                Err(UciError::UnexpectedSyntax(uci.to_string())),
+                game.parse_uci(uci)
+            );
+        }
+
+        // A pawn reaching the last rank has to say what it becomes
+        let game = test_game("3r4/4Pk2/8/8/8/8/8/K7 w - - 0 1");
+        assert_eq!(
+            Err(UciError::IllegalMove("e7e8".to_string())),
+            game.parse_uci("e7e8")
+        );
+    }
+
+    #[test]
+    pub fn game_from_moves() {
+        let game = Game::from_moves(Game::new(), ["e2e4", "c7c5", "g1f3"]).unwrap();
+        assert_eq!(
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+            game.to_fen()
+        );
+
+        assert_eq!(
+            Err(UciError::IllegalMove("e2e4".to_string())),
+            Game::from_moves(Game::new(), ["e2e4", "e2e4"])
+        );
+
+        let knights = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let game = Game::from_moves(Game::new(), knights.iter().chain(knights.iter())).unwrap();
+        assert_eq!(Status::ThreefoldRepetition, game.status());
+    }
+}
@@ -0,0 +1,203 @@
+use std::hash::{Hash, Hasher};
+
+use super::{Game, Side, Space};
+
+// A random number for each thing that can differ between positions
+// A position's key is all of the numbers that apply to it xored together, so a move only has to
+// xor out what it changes and xor in what replaces it
+struct Zobrist {
+    pieces: [[[u64; 64]; 6]; 2],
+    castling: [u64; 4], // White kingside and queenside, then black
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+impl Zobrist {
+    // Filled at compile time with xorshift64*, from a fixed seed so keys are the same every run
+    const fn generate() -> Self {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut zobrist = Zobrist {
+            pieces: [[[0; 64]; 6]; 2],
+            castling: [0; 4],
+            en_passant: [0; 8],
+            black_to_move: 0,
+        };
+
+        let mut side = 0;
+        while side < 2 {
+            let mut piece = 0;
+            while piece < 6 {
+                let mut square = 0;
+                while square < 64 {
+                    state = Zobrist::next(state);
+                    zobrist.pieces[side][piece][square] = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+                    square += 1;
+                }
+                piece += 1;
+            }
+            side += 1;
+        }
+        let mut i = 0;
+        while i < 8 {
+            state = Zobrist::next(state);
+            zobrist.en_passant[i] = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+            if i < 4 {
+                state = Zobrist::next(state);
+                zobrist.castling[i] = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+            }
+            i += 1;
+        }
+        state = Zobrist::next(state);
+        zobrist.black_to_move = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+
+        zobrist
+    }
+
+    const fn next(mut state: u64) -> u64 {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state
+    }
+}
+
+static ZOBRIST: Zobrist = Zobrist::generate();
+
+impl Game {
+    // A 64 bit Zobrist key for the position, equal for any two games with the same side to move,
+    // pieces on the same spaces and the same castling and en passant captures available
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    // Work out the key from nothing, rather than from the last move
+    pub(super) fn compute_key(&self) -> u64 {
+        let mut key = self.castling_key() ^ self.en_passant_key();
+        if self.current_player == Side::Black {
+            key ^= ZOBRIST.black_to_move;
+        }
+        for (space, contents) in self.board.iter().enumerate() {
+            if let Some(contents) = contents {
+                key ^= Game::piece_key(*contents, space);
+            }
+        }
+
+        key
+    }
+
+    pub(super) fn piece_key(contents: Space, space: usize) -> u64 {
+        ZOBRIST.pieces[contents.side as usize][contents.piece as usize]
+            [(space >> 4) * 8 + (space & 0x07)]
+    }
+
+    pub(super) fn castling_key(&self) -> u64 {
+        [
+            self.white.castle_kingside,
+            self.white.castle_queenside,
+            self.black.castle_kingside,
+            self.black.castle_queenside,
+        ]
+        .iter()
+        .zip(ZOBRIST.castling)
+        .filter(|(right, _)| **right)
+        .fold(0, |key, (_, number)| key ^ number)
+    }
+
+    // Only the file matters, and only while a pawn can make the capture
+    pub(super) fn en_passant_key(&self) -> u64 {
+        match self.en_passant_target() {
+            Some(space) => ZOBRIST.en_passant[space & 0x07],
+            None => 0,
+        }
+    }
+
+    pub(super) fn side_key() -> u64 {
+        ZOBRIST.black_to_move
+    }
+}
+
+// Games that are equal are in the same position, so share a key
+impl Hash for Game {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Move;
+
+    fn check_keys(game: &mut Game, depth: usize) {
+        assert_eq!(game.compute_key(), game.key());
+        if depth == 0 {
+            return;
+        }
+
+        for m in game.generate_moves() {
+            let undo = game.do_move(m);
+            check_keys(game, depth - 1);
+            game.undo_move(m, undo);
+        }
+    }
+
+    #[test]
+    pub fn incremental_key_matches_computed() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        ] {
+            check_keys(&mut Game::from_fen(fen.to_string()).unwrap(), 3);
+        }
+    }
+
+    #[test]
+    pub fn transpositions_share_a_key() {
+        let play = |moves: [(usize, usize); 4]| {
+            moves
+                .iter()
+                .fold(Game::new(), |game, (src, dest)| {
+                    game.make_move(Move::new(*src, *dest)).unwrap()
+                })
+                .key()
+        };
+
+        // 1. Nf3 Nf6 2. Nc3 Nc6 and 1. Nc3 Nc6 2. Nf3 Nf6
+        assert_eq!(
+            play([(0x06, 0x25), (0x76, 0x55), (0x01, 0x22), (0x71, 0x52)]),
+            play([(0x01, 0x22), (0x71, 0x52), (0x06, 0x25), (0x76, 0x55)])
+        );
+
+        // Bringing the knights out and back returns to the starting key
+        assert_eq!(
+            Game::new().key(),
+            play([(0x06, 0x25), (0x76, 0x55), (0x25, 0x06), (0x55, 0x76)])
+        );
+    }
+
+    #[test]
+    pub fn key_covers_side_castling_and_en_passant() {
+        let key = |fen: &str| Game::from_fen(fen.to_string()).unwrap().key();
+
+        assert_ne!(
+            key("4k3/8/8/8/8/8/8/4K3 w - -"),
+            key("4k3/8/8/8/8/8/8/4K3 b - -")
+        );
+        assert_ne!(
+            key("r3k3/8/8/8/8/8/8/4K3 b q -"),
+            key("r3k3/8/8/8/8/8/8/4K3 b - -")
+        );
+
+        // En passant only counts when there is a pawn to take it
+        assert_ne!(
+            key("4k3/8/8/8/3pP3/8/8/4K3 b - e3"),
+            key("4k3/8/8/8/3pP3/8/8/4K3 b - -")
+        );
+        assert_eq!(
+            key("4k3/8/8/8/4P3/8/8/4K3 b - e3"),
+            key("4k3/8/8/8/4P3/8/8/4K3 b - -")
+        );
+    }
+}
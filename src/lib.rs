@@ -0,0 +1,10 @@
+#![warn(
+    clippy::all,
+    clippy::restriction,
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::cargo
+)]
+
+// The chess rules and the formats for reading and writing positions and games, which the binary builds on
+pub mod game;
@@ -6,18 +6,90 @@
     clippy::cargo
 )]
 
-use std::mem::size_of;
+use std::{env, fs::File, io::BufReader, mem::size_of, process, time::Duration};
 
-use game::Game;
+use barnacle::game::{bitboard::BitboardGame, perft::suite::PerftSuite, Game};
 
-mod game;
+// Run "perft <depth> [fen]" or "divide <depth> [fen]" to count the move tree, for comparing against other engines
+fn run_perft(command: &str, args: &[String], bitboard: bool) -> Result<(), String> {
+    let depth: usize = match args.first().map(|depth| depth.parse()) {
+        Some(Ok(depth)) => depth,
+        _ => return Err(format!("usage: {} <depth> [fen]", command)),
+    };
+    let game = match args.get(1..).filter(|fen| !fen.is_empty()) {
+        Some(fen) => Game::from_fen(fen.join(" ")).map_err(|error| error.to_string())?,
+        None => Game::new(),
+    };
+
+    match (command, bitboard) {
+        ("perft", false) => println!("{}", game.perft(depth)),
+        ("perft", true) => println!("{}", BitboardGame::from(&game).perft(depth)),
+        (_, false) => println!("{}", game.divide(depth)),
+        (_, true) => println!("{}", BitboardGame::from(&game).divide(depth)),
+    }
+    Ok(())
+}
+
+// Run "suite <file> [max depth] [seconds per position]" over an EPD file of perft counts, like perftsuite.epd
+fn run_suite(args: &[String], bitboard: bool) -> Result<(), String> {
+    let usage = || "usage: suite <file> [max depth] [seconds per position]".to_string();
+    let path = args.first().ok_or_else(usage)?;
+    let max_depth = match args.get(1) {
+        Some(depth) => depth.parse().map_err(|_| usage())?,
+        None => 6,
+    };
+    let time_limit = match args.get(2) {
+        Some(seconds) => seconds
+            .parse()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(usage)?,
+        None => Duration::from_secs(10),
+    };
+
+    let file = File::open(path).map_err(|error| format!("can't open {}: {}", path, error))?;
+    let (mut passed, mut failed) = (0, 0);
+    let mut suite = PerftSuite::new(BufReader::new(file), max_depth, time_limit);
+    if bitboard {
+        suite = suite.counting_with(|game, depth| BitboardGame::from(game).perft(depth));
+    }
+    for result in suite {
+        let result = result.map_err(|error| error.to_string())?;
+        println!("{}", result);
+        if result.passed() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} positions failed", failed)),
+    }
+}
 
 fn main() {
-    let board = Game::new();
+    // Any command can count with the bitboard representation instead, to check the two against each other
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let bitboard = args.iter().any(|arg| arg == "--bitboard");
+    args.retain(|arg| arg != "--bitboard");
 
-    board.generate_ply();
+    if let Some(command @ ("perft" | "divide" | "suite")) = args.first().map(String::as_str) {
+        let result = match command {
+            "suite" => run_suite(&args[1..], bitboard),
+            _ => run_perft(command, &args[1..], bitboard),
+        };
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        return;
+    }
 
     println!("{}", size_of::<Game>());
+    println!("{}", size_of::<BitboardGame>());
 }
 
 // 256 bytes for a simple repr